@@ -40,7 +40,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         queue.clone(),
     )?;
 
-    capturer.start_capture();
+    capturer.start_capture()?;
 
     let start = Instant::now();
     let mut saved = 0usize;
@@ -59,7 +59,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         saved += 1;
     }
 
-    capturer.stop_capture();
+    capturer.stop_capture()?;
 
     println!("Captured {} frames in {:?}", saved, start.elapsed());
 
@@ -1,16 +1,36 @@
+pub mod backend;
+mod cfr;
 pub mod engine;
+mod options;
+mod stats;
 
-use std::{error::Error, sync::mpsc};
+use std::{
+    fmt,
+    future::poll_fn,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
 
-use engine::ChannelItem;
+use cfr::ConstantFrameRate;
+use engine::synthetic::SyntheticEngine;
+use futures::{Stream, executor::block_on, task::noop_waker_ref};
+use stats::DeliveryStats;
 
 use crate::{
-    frame::{Frame, FrameType},
+    frame::{Frame, FrameType, PixelFormat, PoolStats},
     has_permission, is_supported,
     targets::Target,
 };
 
+pub use backend::{Capabilities, CaptureBackend};
+pub use engine::channel::QueuePolicy;
 pub use engine::get_output_frame_size;
+pub use options::{OptionsBuilder, OptionsError, UnsupportedOption};
+pub use stats::{CaptureStats, DroppedFrames, LatencyPercentiles, SourceDrops};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub enum Resolution {
@@ -41,6 +61,106 @@ impl Resolution {
     }
 }
 
+/// How the sources picked in the portal dialog are captured, see [`Options::multi_source`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultiSource {
+    /// The user picks a single source
+    #[default]
+    Single,
+    /// The user may pick several sources, each delivered as its own frames tagged with the
+    /// index of the source. All sources share one clock, so their pts line up
+    Separate,
+    /// The user may pick several monitors, composited into one frame of the virtual desktop
+    /// using the position the portal reports for each. Monitors are scaled to their logical
+    /// size
+    Composite,
+}
+
+/// Kind of source the user may pick in the portal dialog, see [`Options::source_kinds`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Monitor,
+    Window,
+    /// A monitor that only exists for the capture, e.g. to extend the desktop to a remote
+    /// screen
+    Virtual,
+}
+
+/// Input device the consumer may control alongside the capture, see
+/// [`Options::input_devices`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDevice {
+    Keyboard,
+    Pointer,
+    Touchscreen,
+}
+
+/// Window the portal dialog is modal to, see [`Options::parent_window`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParentWindow {
+    /// XID of an X11 window
+    X11(u32),
+    /// Handle of a Wayland toplevel exported through xdg-foreign
+    Wayland(String),
+}
+
+impl fmt::Display for ParentWindow {
+    /// Formats the identifier the portal expects, e.g. `x11:0x1a00004`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParentWindow::X11(xid) => write!(f, "x11:{xid:#x}"),
+            ParentWindow::Wayland(handle) => write!(f, "wayland:{handle}"),
+        }
+    }
+}
+
+/// Format of the video frames a capture delivers, see [`Capturer::wait_for_format`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedFormat {
+    pub pixel_format: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How long the portal remembers the sources the user picked, see [`Options::persist_mode`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PersistMode {
+    /// The permission ends with the capture
+    #[default]
+    DoNot,
+    /// The permission lasts while the application is running
+    Application,
+    /// The permission lasts until the user revokes it
+    Persistent,
+}
+
+/// Cancels a pending portal dialog from another thread, see [`Options::portal_cancel`].
+///
+/// Clones share their state, keep one and put another into the options.
+#[derive(Debug, Clone, Default)]
+pub struct PortalCancel(Arc<AtomicBool>);
+
+impl PortalCancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Closes the portal dialog, [`Capturer::build`] then fails with
+    /// [`CaptureError::Cancelled`]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.0)
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Point {
     pub x: f64,
@@ -59,110 +179,332 @@ pub struct Area {
 }
 
 /// Options passed to the screen capturer
+///
+/// Prefer [`Options::builder`], which rejects options the backend would ignore.
 #[derive(Debug, Default, Clone)]
 pub struct Options {
     pub fps: u32,
+    /// Deliver video frames at exactly `fps`, repeating the last frame while the source has
+    /// nothing new and dropping surplus frames. Timestamps are snapped to the frame grid
+    pub constant_frame_rate: bool,
     pub show_cursor: bool,
+    /// Deliver the cursor as [`CursorInfo`](crate::frame::CursorInfo) on each frame instead of
+    /// drawing it into the pixels, takes precedence over `show_cursor`. Only implemented for
+    /// Linux currently
+    pub cursor_metadata: bool,
     pub show_highlight: bool,
+    /// Source to capture. On Linux sources are picked in the portal dialog, so only the
+    /// target returned by [`get_main_display`](crate::get_main_display) is accepted
     pub target: Option<Target>,
     pub crop_area: Option<Area>,
+    /// Pixel format of the video frames. Only honoured by the Windows and macOS engines,
+    /// Linux delivers the format PipeWire negotiated
     pub output_type: FrameType,
+    /// Only implemented for Windows and macOS currently
     pub output_resolution: Resolution,
     // excluded targets will only work on macOS
     pub excluded_targets: Option<Vec<Target>>,
     /// Only implemented for Windows and macOS currently
     pub captures_audio: bool,
+    /// Not implemented by any engine currently, setting it fails validation
     pub exclude_current_process_audio: bool,
+    /// What happens to captured frames when they are not consumed fast enough
+    pub queue_policy: QueuePolicy,
+    /// Number of frame buffers kept for reuse after the consumer drops a frame, `0` disables
+    /// recycling. Only used by the Windows and Linux engines currently
+    pub frame_pool_size: usize,
+    /// Only implemented for Linux currently
+    pub multi_source: MultiSource,
+    /// Token from [`Capturer::restore_token`] of an earlier capture, restores its sources
+    /// without showing the portal dialog. Needs ScreenCast portal version 4 or newer,
+    /// [`Capturer::build`] fails on older ones. Only implemented for Linux currently
+    pub restore_token: Option<String>,
+    /// Asks the portal for a restore token of the picked sources, see `restore_token`
+    pub persist_mode: PersistMode,
+    /// Kinds of sources offered in the portal dialog, empty offers every kind the portal
+    /// supports. Only implemented for Linux currently
+    pub source_kinds: Vec<SourceKind>,
+    /// Input devices to control through the RemoteDesktop portal, requested in the same
+    /// dialog as the sources, see [`Capturer::remote_desktop`]. Empty only captures. Only
+    /// implemented for Linux currently
+    pub input_devices: Vec<InputDevice>,
+    /// Window of the application the portal dialog is modal to, so it does not open behind
+    /// it. Only used on Linux
+    pub parent_window: Option<ParentWindow>,
+    /// How long each portal request may take, including the time the user spends in the
+    /// dialog. `None` waits two minutes. Only used on Linux
+    pub portal_timeout: Option<Duration>,
+    /// Cancels the portal dialog while [`Capturer::build`] waits for it. Only used on Linux
+    pub portal_cancel: PortalCancel,
 }
 
 /// Screen capturer class
 pub struct Capturer {
-    engine: engine::Engine,
-    rx: mpsc::Receiver<ChannelItem>,
-}
-
-#[derive(Debug)]
-pub enum CapturerBuildError {
-    NotSupported,
-    PermissionNotGranted,
+    backend: Box<dyn CaptureBackend>,
+    cfr: Option<ConstantFrameRate>,
+    deliveries: DeliveryStats,
 }
 
-impl std::fmt::Display for CapturerBuildError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CapturerBuildError::NotSupported => write!(f, "Screen capturing is not supported"),
-            CapturerBuildError::PermissionNotGranted => {
-                write!(f, "Permission to capture the screen is not granted")
+/// Streams what `poll` resolves to. Once it resolves to `None`, the error reported by
+/// `error` is yielded before the stream terminates.
+pub(crate) fn ending_with_error<T, E>(
+    mut poll: impl FnMut(&mut Context<'_>) -> Poll<Option<Result<T, E>>>,
+    error: impl Fn() -> Option<E>,
+) -> impl Stream<Item = Result<T, E>> {
+    let mut finished = false;
+    futures::stream::poll_fn(move |cx| {
+        if finished {
+            return Poll::Ready(None);
+        }
+        match poll(cx) {
+            Poll::Ready(None) => {
+                finished = true;
+                Poll::Ready(error().map(Err))
             }
+            poll => poll,
         }
-    }
+    })
 }
 
-impl Error for CapturerBuildError {}
+// Capturers are built on one thread and often moved to a dedicated capture thread
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Capturer>();
+};
+
+/// Errors that may occur while building a [`Capturer`] or retrieving frames from it
+#[derive(thiserror::Error, Debug)]
+pub enum CaptureError {
+    #[error("Screen capturing is not supported")]
+    NotSupported,
+    #[error("Permission to capture the screen is not granted")]
+    PermissionNotGranted,
+    /// The xdg-desktop-portal request failed or was rejected
+    #[error("Portal request failed: {0}")]
+    Portal(String),
+    /// The user cancelled the portal dialog, or it was cancelled through
+    /// [`Options::portal_cancel`]
+    #[error("Portal request cancelled")]
+    Cancelled,
+    /// The named portal request got no response within [`Options::portal_timeout`]
+    #[error("Portal request timed out: {0}")]
+    PortalTimeout(String),
+    /// The portal ended the request without a result, e.g. because the dialog was closed by
+    /// the system
+    #[error("Portal request ended without a result")]
+    PortalEnded,
+    /// PipeWire failed to set up or run the stream
+    #[error("PipeWire error: {0}")]
+    PipeWire(String),
+    /// The negotiated frame format could not be parsed or is not supported
+    #[error("Unsupported frame format: {0}")]
+    Format(String),
+    /// The capture stream went away without reporting a reason
+    #[error("Capture stream disconnected")]
+    Disconnected,
+    /// Any other error reported by the platform capture engine
+    #[error("Capture engine error: {0}")]
+    Backend(String),
+}
+
+/// Kept for backwards compatibility, use [`CaptureError`] instead
+pub type CapturerBuildError = CaptureError;
 
 impl Capturer {
     /// Build a new [Capturer] instance with the provided options
-    pub fn build(options: Options) -> Result<Capturer, CapturerBuildError> {
+    pub fn build(options: Options) -> Result<Capturer, CaptureError> {
+        // Synthetic targets don't touch the platform, so they work anywhere
+        if let Some(Target::Synthetic(source)) = &options.target {
+            return Ok(
+                Capturer::with_backend(SyntheticEngine::new(source.clone(), &options)?)
+                    .paced(&options),
+            );
+        }
+
         if !is_supported() {
-            return Err(CapturerBuildError::NotSupported);
+            return Err(CaptureError::NotSupported);
         }
 
         if !has_permission() {
-            return Err(CapturerBuildError::PermissionNotGranted);
+            return Err(CaptureError::PermissionNotGranted);
         }
 
-        let (tx, rx) = mpsc::channel();
-        let engine = engine::Engine::new(&options, tx);
+        Ok(Capturer::with_backend(engine::Engine::new(&options)?).paced(&options))
+    }
+
+    /// Build a [Capturer] that pulls its frames from a custom [`CaptureBackend`]
+    pub fn with_backend(backend: impl CaptureBackend) -> Capturer {
+        Capturer {
+            backend: Box::new(backend),
+            cfr: None,
+            deliveries: DeliveryStats::default(),
+        }
+    }
 
-        Ok(Capturer { engine, rx })
+    /// Applies [`Options::constant_frame_rate`]
+    fn paced(mut self, options: &Options) -> Capturer {
+        if options.constant_frame_rate {
+            self.cfr = Some(ConstantFrameRate::new(options.fps));
+        }
+        self
     }
 
     // TODO
     // Prevent starting capture if already started
     /// Start capturing the frames
-    pub fn start_capture(&mut self) {
-        self.engine.start();
+    pub fn start_capture(&mut self) -> Result<(), CaptureError> {
+        self.backend.start()
     }
 
     /// Stop the capturer
-    pub fn stop_capture(&mut self) {
-        self.engine.stop();
+    pub fn stop_capture(&mut self) -> Result<(), CaptureError> {
+        self.backend.stop()
     }
 
     /// Get the next captured frame
-    pub fn get_next_frame(&self) -> Result<Frame, mpsc::RecvError> {
-        loop {
-            let res = self.rx.recv()?;
-
-            if let Some(frame) = self.engine.process_channel_item(res) {
-                return Ok(frame);
-            }
-        }
+    ///
+    /// Returns the error that ended the capture once the engine stops delivering frames.
+    pub fn get_next_frame(&self) -> Result<Frame, CaptureError> {
+        block_on(poll_fn(|cx| self.poll_next_frame(cx))).ok_or_else(|| self.disconnect_error())
     }
 
     /// Attempts to return the next captured frame without blocking.
     ///
     /// Processes all currently available channel items until a usable frame is found.
     /// Returns `Ok(None)` if the channel is empty; filtered items are processed transparently.
-    /// Returns `Err(mpsc::RecvError)` if the capture channel has been disconnected.
-    pub fn try_get_next_frame(&self) -> Result<Option<Frame>, mpsc::RecvError> {
-        loop {
-            match self.rx.try_recv() {
-                Ok(res) => {
-                    if let Some(frame) = self.engine.process_channel_item(res) {
-                        return Ok(Some(frame));
-                    }
-                    // Item filtered, try next without blocking
-                }
-                Err(mpsc::TryRecvError::Empty) => return Ok(None),
-                Err(mpsc::TryRecvError::Disconnected) => return Err(mpsc::RecvError),
-            }
+    /// Returns an error if the capture channel has been disconnected.
+    pub fn try_get_next_frame(&self) -> Result<Option<Frame>, CaptureError> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        match self.poll_next_frame(&mut cx) {
+            Poll::Ready(Some(frame)) => Ok(Some(frame)),
+            Poll::Ready(None) => Err(self.disconnect_error()),
+            Poll::Pending => Ok(None),
         }
     }
 
+    /// Returns a [`Stream`] of captured frames for use from async code.
+    ///
+    /// The polling task is woken as soon as the engine delivers a new frame, so no blocking
+    /// thread is needed. If the capture ends because of an error, the error is yielded
+    /// before the stream terminates.
+    pub fn frames(&self) -> impl Stream<Item = Result<Frame, CaptureError>> + '_ {
+        ending_with_error(
+            |cx| self.poll_next_frame(cx).map(|frame| frame.map(Ok)),
+            || self.backend.error(),
+        )
+    }
+
+    /// Pulls the next frame out of the backend and records its delivery
+    fn poll_next_frame(&self, cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+        let poll = match &self.cfr {
+            Some(cfr) => cfr.poll_next_frame(&*self.backend, cx),
+            None => self.backend.poll_next_frame(cx),
+        };
+        if let Poll::Ready(Some(Frame::Video(frame))) = &poll {
+            let latency = SystemTime::now()
+                .duration_since(frame.display_time())
+                .unwrap_or_default();
+            self.deliveries.record(Instant::now(), latency);
+        }
+        poll
+    }
+
+    fn disconnect_error(&self) -> CaptureError {
+        self.backend.error().unwrap_or(CaptureError::Disconnected)
+    }
+
+    /// Allocation statistics of the buffers behind captured frames, see
+    /// [`Options::frame_pool_size`]
+    pub fn pool_stats(&self) -> PoolStats {
+        self.backend.pool_stats()
+    }
+
+    /// Number of frames dropped so far because of [`Options::queue_policy`]
+    pub fn dropped_frames(&self) -> u64 {
+        self.backend.dropped_frames()
+    }
+
+    /// Frames delivered and dropped so far, the current frame rate and the latency from
+    /// capture to delivery
+    pub fn stats(&self) -> CaptureStats {
+        let source = self.source_drops();
+        self.deliveries.snapshot(
+            Instant::now(),
+            DroppedFrames {
+                out_of_buffers: source.out_of_buffers,
+                corrupted: source.corrupted,
+                queue: self.backend.dropped_frames(),
+                frame_rate: self.cfr.as_ref().map_or(0, ConstantFrameRate::dropped),
+            },
+        )
+    }
+
+    /// Token that restores the sources of this capture through
+    /// [`Options::restore_token`], only handed out when [`Options::persist_mode`] is set.
+    ///
+    /// A token can be used once, store the one of every new capture.
+    pub fn restore_token(&self) -> Option<String> {
+        #[cfg(target_os = "linux")]
+        if let Some(linux) = self.linux_engine() {
+            return linux.restore_token();
+        }
+        None
+    }
+
+    /// Targets being captured, once known.
+    ///
+    /// On Linux these are the sources the user picked in the portal dialog, available right
+    /// after [`Capturer::build`]. They are in the order of
+    /// [`VideoFrame::source`](crate::frame::VideoFrame::source)
+    pub fn targets(&self) -> Vec<Target> {
+        #[cfg(target_os = "linux")]
+        if let Some(linux) = self.linux_engine() {
+            return linux.targets();
+        }
+        Vec::new()
+    }
+
+    /// Injects input into the captured session, when [`Options::input_devices`] were granted
+    #[cfg(target_os = "linux")]
+    pub fn remote_desktop(&self) -> Option<Arc<engine::linux::RemoteDesktopSession>> {
+        self.linux_engine()?.remote_desktop()
+    }
+
+    /// Frames the backend lost before they were queued, only the portal engine counts them
+    fn source_drops(&self) -> SourceDrops {
+        #[cfg(target_os = "linux")]
+        if let Some(linux) = self.linux_engine() {
+            return linux.source_drops();
+        }
+        SourceDrops::default()
+    }
+
+    /// The portal engine, unless the frames come from another backend
+    #[cfg(target_os = "linux")]
+    fn linux_engine(&self) -> Option<&engine::linux::LinuxCapturer> {
+        let backend: &dyn std::any::Any = &*self.backend;
+        backend
+            .downcast_ref::<engine::Engine>()
+            .map(|engine| &engine.linux)
+    }
+
+    /// Features the backend of this capturer honours
+    pub fn capabilities(&self) -> Capabilities {
+        self.backend.capabilities()
+    }
+
+    /// Blocks until the format of the video frames is known, so an encoder can be set up
+    /// before the first frame arrives.
+    ///
+    /// On Linux the format is negotiated with PipeWire right after [`Capturer::build`], without
+    /// starting the capture. With [`MultiSource::Separate`] it is the format of the first source
+    pub fn wait_for_format(&self) -> Result<NegotiatedFormat, CaptureError> {
+        self.backend.wait_for_format()
+    }
+
     /// Get the dimensions the frames will be captured in
     pub fn get_output_frame_size(&mut self) -> [u32; 2] {
-        self.engine.get_output_frame_size()
+        self.backend.output_frame_size()
     }
 
     pub fn raw(&'_ self) -> RawCapturer<'_> {
@@ -0,0 +1,197 @@
+//! Extension point for capture sources.
+//!
+//! [`Capturer`](super::Capturer) drives any [`CaptureBackend`], so new sources such as an X11
+//! grabber, a file replay or a network stream can be plugged in without forking the crate.
+//! The built-in platform engines and [`Synthetic`](crate::Synthetic) targets use the same trait.
+
+use std::{
+    any::Any,
+    task::{Context, Poll},
+};
+
+use super::{CaptureError, NegotiatedFormat};
+use crate::frame::{Frame, PoolStats};
+
+/// Features a [`CaptureBackend`] honours, used to reject options it would silently ignore
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Captures the given `target`, rather than only a source the user picks
+    pub target: bool,
+    /// Delivers [`Frame::Audio`] when `captures_audio` is set
+    pub audio: bool,
+    /// Honours `exclude_current_process_audio`
+    pub exclude_process_audio: bool,
+    /// Honours `crop_area`
+    pub crop: bool,
+    /// Converts the frames to `output_type`, other than the default YUV
+    pub output_type: bool,
+    /// Scales the frames to `output_resolution`
+    pub scaling: bool,
+    /// Honours `excluded_targets`
+    pub excluded_targets: bool,
+    /// Honours `show_cursor`
+    pub cursor: bool,
+    /// Honours `cursor_metadata`
+    pub cursor_metadata: bool,
+    /// Honours `show_highlight`
+    pub highlight: bool,
+    /// Honours `multi_source`
+    pub multi_source: bool,
+    /// Honours `restore_token` and `persist_mode`
+    pub restore_session: bool,
+    /// Honours `source_kinds`
+    pub source_kinds: bool,
+    /// Honours `input_devices`
+    pub input_devices: bool,
+}
+
+/// A source of captured frames.
+///
+/// Backends usually run their capture on a separate thread and hand frames over through a
+/// queue. [`CaptureBackend::poll_next_frame`] is the only way frames are pulled out, both for
+/// the blocking and the async [`Capturer`](super::Capturer) APIs, so a backend must wake the
+/// registered waker whenever a frame arrives or the capture ends.
+pub trait CaptureBackend: Any + Send {
+    /// Starts delivering frames
+    fn start(&mut self) -> Result<(), CaptureError>;
+
+    /// Stops delivering frames
+    fn stop(&mut self) -> Result<(), CaptureError>;
+
+    /// Polls for the next frame.
+    ///
+    /// Returns `Poll::Ready(None)` once the backend will not deliver any more frames.
+    fn poll_next_frame(&self, cx: &mut Context<'_>) -> Poll<Option<Frame>>;
+
+    /// Dimensions of the frames the backend delivers
+    fn output_frame_size(&self) -> [u32; 2];
+
+    /// Blocks until the format of the video frames is known
+    fn wait_for_format(&self) -> Result<NegotiatedFormat, CaptureError> {
+        Err(CaptureError::NotSupported)
+    }
+
+    /// Features this backend honours
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Error that ended the capture, if any
+    fn error(&self) -> Option<CaptureError> {
+        None
+    }
+
+    /// Number of frames dropped because the consumer did not keep up
+    fn dropped_frames(&self) -> u64 {
+        0
+    }
+
+    /// Allocation statistics of the buffers behind delivered frames
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::{
+        capturer::Capturer,
+        frame::{BGRAFrame, VideoFrame},
+    };
+
+    /// Replays a fixed list of frames, like a file replay backend would
+    struct Replay {
+        frames: Mutex<VecDeque<Frame>>,
+        fails: bool,
+    }
+
+    impl CaptureBackend for Replay {
+        fn start(&mut self) -> Result<(), CaptureError> {
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), CaptureError> {
+            Ok(())
+        }
+
+        fn poll_next_frame(&self, _cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+            Poll::Ready(self.frames.lock().unwrap().pop_front())
+        }
+
+        fn output_frame_size(&self) -> [u32; 2] {
+            [2, 1]
+        }
+
+        fn error(&self) -> Option<CaptureError> {
+            self.fails
+                .then(|| CaptureError::PipeWire(String::from("stream error")))
+        }
+    }
+
+    fn frame() -> Frame {
+        Frame::Video(VideoFrame::BGRA(BGRAFrame {
+            display_time: SystemTime::UNIX_EPOCH,
+            pts: Duration::ZERO,
+            sequence: 0,
+            width: 2,
+            height: 1,
+            data: vec![0; 8].into(),
+            stride: 8,
+            damage: None,
+            cursor: None,
+            repeat: false,
+            source: 0,
+        }))
+    }
+
+    #[test]
+    fn capturer_drives_custom_backend() {
+        let mut capturer = Capturer::with_backend(Replay {
+            frames: Mutex::new(VecDeque::from([frame(), frame()])),
+            fails: false,
+        });
+        capturer.start_capture().unwrap();
+
+        assert_eq!(capturer.get_output_frame_size(), [2, 1]);
+        assert!(capturer.get_next_frame().is_ok());
+        assert!(matches!(capturer.try_get_next_frame(), Ok(Some(_))));
+        assert!(matches!(
+            capturer.get_next_frame(),
+            Err(CaptureError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn capturer_counts_delivered_frames() {
+        let capturer = Capturer::with_backend(Replay {
+            frames: Mutex::new(VecDeque::from([frame(), frame()])),
+            fails: false,
+        });
+        while capturer.get_next_frame().is_ok() {}
+
+        let stats = capturer.stats();
+        assert_eq!(stats.frames_delivered, 2);
+        assert_eq!(stats.dropped.total(), 0);
+        assert!(stats.latency.is_some());
+    }
+
+    #[test]
+    fn frames_end_with_backend_error() {
+        let capturer = Capturer::with_backend(Replay {
+            frames: Mutex::new(VecDeque::from([frame()])),
+            fails: true,
+        });
+        let frames: Vec<_> = futures::executor::block_on_stream(capturer.frames()).collect();
+        assert!(matches!(
+            frames[..],
+            [Ok(_), Err(CaptureError::PipeWire(_))]
+        ));
+    }
+}
@@ -0,0 +1,359 @@
+//! Constant frame rate output, see [`Options::constant_frame_rate`](super::Options).
+//!
+//! Video frames are handed out on a grid of `1 / fps` slots that starts at the first frame.
+//! A frame goes into the slot nearest to its pts. A slot that gets several frames keeps the
+//! newest one, and a slot that gets none by the time it is due repeats the previous frame.
+//! Audio frames are passed through as they arrive.
+
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::CaptureBackend;
+use crate::frame::{Frame, VideoFrame};
+
+/// Where the grid starts, taken from the first video frame
+#[derive(Debug, Clone, Copy)]
+struct Origin {
+    received: Instant,
+    pts: Duration,
+    display_time: SystemTime,
+}
+
+#[derive(Default)]
+struct Grid {
+    origin: Option<Origin>,
+    /// Slot the next frame is delivered in
+    next: u64,
+    /// Newest frame for the next slot or a slot that already passed
+    held: Option<(u64, VideoFrame)>,
+    /// Frame for a later slot, received while looking for the next one
+    ahead: Option<(u64, VideoFrame)>,
+    /// Last frame delivered that was not a repeat
+    last: Option<VideoFrame>,
+    /// The backend will not deliver any more frames
+    ended: bool,
+    dropped: u64,
+}
+
+impl Grid {
+    fn held_slot(&self) -> Option<u64> {
+        self.held.as_ref().map(|(slot, _)| *slot)
+    }
+
+    fn ahead_slot(&self) -> Option<u64> {
+        self.ahead.as_ref().map(|(slot, _)| *slot)
+    }
+}
+
+/// Paces the video frames of a backend to a fixed frame rate
+pub(crate) struct ConstantFrameRate {
+    interval: Duration,
+    grid: Mutex<Grid>,
+    timer: Timer,
+}
+
+impl ConstantFrameRate {
+    pub(crate) fn new(fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1),
+            grid: Mutex::default(),
+            timer: Timer::spawn(),
+        }
+    }
+
+    /// Number of frames dropped because the source was faster than the frame rate
+    pub(crate) fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    /// Polls `backend` for frames and returns the frame of the next slot once it is known
+    pub(crate) fn poll_next_frame(
+        &self,
+        backend: &dyn CaptureBackend,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Frame>> {
+        let poll = self.poll_at(backend, cx, Instant::now());
+        if poll.is_pending() {
+            let grid = self.lock();
+            if let Some(origin) = grid.origin {
+                self.timer
+                    .wake_at(self.deadline(origin, grid.next), cx.waker());
+            }
+        }
+        poll
+    }
+
+    fn poll_at(
+        &self,
+        backend: &dyn CaptureBackend,
+        cx: &mut Context<'_>,
+        now: Instant,
+    ) -> Poll<Option<Frame>> {
+        let mut grid = self.lock();
+        let grid = &mut *grid;
+
+        if grid.ahead_slot().is_some_and(|slot| slot <= grid.next) {
+            grid.held = grid.ahead.take();
+        }
+
+        // Collect frames until the next slot is known to be complete
+        while grid.ahead.is_none() && !grid.ended && grid.held_slot() != Some(grid.next) {
+            match backend.poll_next_frame(cx) {
+                Poll::Ready(Some(Frame::Video(frame))) => {
+                    let origin = *grid.origin.get_or_insert(Origin {
+                        received: now,
+                        pts: frame.pts(),
+                        display_time: frame.display_time(),
+                    });
+                    let slot = self.slot_of(frame.pts().saturating_sub(origin.pts));
+                    if slot > grid.next {
+                        grid.ahead = Some((slot, frame));
+                    } else if grid.held.replace((slot, frame)).is_some() {
+                        grid.dropped += 1;
+                    }
+                }
+                Poll::Ready(Some(audio)) => return Poll::Ready(Some(audio)),
+                Poll::Ready(None) => grid.ended = true,
+                Poll::Pending => break,
+            }
+        }
+
+        let Some(origin) = grid.origin else {
+            return if grid.ended {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        };
+        if grid.ended && grid.held.is_none() && grid.ahead.is_none() {
+            return Poll::Ready(None);
+        }
+
+        let slot = grid.next;
+        let complete = grid.ahead.is_some()
+            || grid.ended
+            || grid.held_slot() == Some(slot)
+            || now >= self.deadline(origin, slot);
+        if !complete {
+            return Poll::Pending;
+        }
+
+        let mut frame = match grid.held.take() {
+            Some((_, frame)) => {
+                grid.last = Some(frame.clone());
+                frame
+            }
+            None => match &grid.last {
+                Some(last) => last.to_repeat(),
+                None => return Poll::Pending,
+            },
+        };
+        let offset = self.offset(slot);
+        frame.set_timing(origin.display_time + offset, origin.pts + offset, slot);
+        grid.next += 1;
+
+        Poll::Ready(Some(Frame::Video(frame)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Grid> {
+        self.grid.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Slot nearest to `offset` from the start of the grid
+    fn slot_of(&self, offset: Duration) -> u64 {
+        let interval = self.interval.as_nanos();
+        ((offset.as_nanos() + interval / 2) / interval) as u64
+    }
+
+    /// Offset of `slot` from the start of the grid
+    fn offset(&self, slot: u64) -> Duration {
+        Duration::from_nanos((self.interval.as_nanos() * u128::from(slot)) as u64)
+    }
+
+    /// Time at which `slot` is repeated if no frame arrived for it, half a slot late to
+    /// absorb jitter in the delivery of the source
+    fn deadline(&self, origin: Origin, slot: u64) -> Instant {
+        origin.received + self.offset(slot) + self.interval / 2
+    }
+}
+
+#[derive(Default)]
+struct Alarm {
+    at: Option<(Instant, Waker)>,
+    stopped: bool,
+}
+
+/// Wakes the consumer when a slot is due, since no frame from the source will
+struct Timer {
+    alarm: Arc<(Mutex<Alarm>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Timer {
+    fn spawn() -> Self {
+        let alarm = Arc::new((Mutex::new(Alarm::default()), Condvar::new()));
+        let handle = std::thread::spawn({
+            let alarm = alarm.clone();
+            move || ring(&alarm.0, &alarm.1)
+        });
+        Self {
+            alarm,
+            handle: Some(handle),
+        }
+    }
+
+    fn wake_at(&self, at: Instant, waker: &Waker) {
+        let (alarm, condvar) = &*self.alarm;
+        alarm.lock().unwrap_or_else(PoisonError::into_inner).at = Some((at, waker.clone()));
+        condvar.notify_one();
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let (alarm, condvar) = &*self.alarm;
+        alarm.lock().unwrap_or_else(PoisonError::into_inner).stopped = true;
+        condvar.notify_one();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Wakes the waker of each alarm once it is due, until the timer is dropped
+fn ring(alarm: &Mutex<Alarm>, condvar: &Condvar) {
+    let mut guard = alarm.lock().unwrap_or_else(PoisonError::into_inner);
+    while !guard.stopped {
+        guard = match &guard.at {
+            Some((at, _)) if *at <= Instant::now() => {
+                let waker = guard.at.take().map(|(_, waker)| waker);
+                drop(guard);
+                waker.into_iter().for_each(Waker::wake);
+                alarm.lock().unwrap_or_else(PoisonError::into_inner)
+            }
+            Some((at, _)) => {
+                let timeout = at.saturating_duration_since(Instant::now());
+                condvar
+                    .wait_timeout(guard, timeout)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => condvar.wait(guard).unwrap_or_else(PoisonError::into_inner),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque};
+
+    use futures::task::noop_waker_ref;
+
+    use super::*;
+    use crate::{capturer::CaptureError, frame::BGRAFrame};
+
+    /// Hands out the frames queued so far, then waits for more
+    #[derive(Default)]
+    struct Source {
+        frames: RefCell<VecDeque<Frame>>,
+    }
+
+    impl Source {
+        /// Queues a frame identified by its width
+        fn push(&self, id: i32, pts_ms: u64) {
+            self.frames
+                .borrow_mut()
+                .push_back(Frame::Video(VideoFrame::BGRA(BGRAFrame {
+                    display_time: SystemTime::UNIX_EPOCH,
+                    pts: Duration::from_millis(pts_ms),
+                    sequence: 0,
+                    width: id,
+                    height: 1,
+                    data: vec![0; 4].into(),
+                    stride: 4,
+                    damage: None,
+                    cursor: None,
+                    repeat: false,
+                    source: 0,
+                })));
+        }
+    }
+
+    impl CaptureBackend for Source {
+        fn start(&mut self) -> Result<(), CaptureError> {
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), CaptureError> {
+            Ok(())
+        }
+
+        fn poll_next_frame(&self, _cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+            match self.frames.borrow_mut().pop_front() {
+                Some(frame) => Poll::Ready(Some(frame)),
+                None => Poll::Pending,
+            }
+        }
+
+        fn output_frame_size(&self) -> [u32; 2] {
+            [1, 1]
+        }
+    }
+
+    /// Polls at `ms` after `start` and returns the id, pts in ms and repeat flag of the frame
+    fn poll(
+        cfr: &ConstantFrameRate,
+        source: &Source,
+        start: Instant,
+        ms: u64,
+    ) -> Option<(i32, u128, bool)> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        match cfr.poll_at(source, &mut cx, start + Duration::from_millis(ms)) {
+            Poll::Ready(Some(Frame::Video(VideoFrame::BGRA(frame)))) => {
+                Some((frame.width, frame.pts.as_millis(), frame.repeat))
+            }
+            Poll::Pending => None,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn repeats_last_frame_when_source_is_idle() {
+        let cfr = ConstantFrameRate::new(10);
+        let source = Source::default();
+        let start = Instant::now();
+
+        source.push(1, 5_000);
+        assert_eq!(poll(&cfr, &source, start, 0), Some((1, 5_000, false)));
+        // The repeat waits half a slot for a late frame
+        assert_eq!(poll(&cfr, &source, start, 120), None);
+        assert_eq!(poll(&cfr, &source, start, 150), Some((1, 5_100, true)));
+        assert_eq!(poll(&cfr, &source, start, 250), Some((1, 5_200, true)));
+    }
+
+    #[test]
+    fn drops_surplus_frames_and_snaps_to_grid() {
+        let cfr = ConstantFrameRate::new(10);
+        let source = Source::default();
+        let start = Instant::now();
+
+        source.push(1, 0);
+        assert_eq!(poll(&cfr, &source, start, 0), Some((1, 0, false)));
+
+        source.push(2, 30);
+        source.push(3, 60);
+        source.push(4, 130);
+        source.push(5, 260);
+        // 2 and 3 both fall into the second slot, only the newer one is kept
+        assert_eq!(poll(&cfr, &source, start, 10), Some((3, 100, false)));
+        // 4 arrived too late for its slot, it takes the next one since 5 belongs further on
+        assert_eq!(poll(&cfr, &source, start, 20), Some((4, 200, false)));
+        assert_eq!(poll(&cfr, &source, start, 30), Some((5, 300, false)));
+        assert_eq!(poll(&cfr, &source, start, 40), None);
+        assert_eq!(cfr.dropped(), 1);
+    }
+}
@@ -1,7 +1,17 @@
-use std::sync::mpsc;
+use std::task::{Context, Poll};
 
-use super::Options;
-use crate::frame::Frame;
+use super::{
+    CaptureError, NegotiatedFormat, Options,
+    backend::{Capabilities, CaptureBackend},
+};
+use crate::{
+    frame::{Frame, PoolStats},
+    targets::Target,
+};
+
+pub mod channel;
+pub(crate) mod clock;
+pub mod synthetic;
 
 #[cfg(target_os = "macos")]
 pub mod mac;
@@ -20,7 +30,37 @@ pub type ChannelItem = (
 #[cfg(not(target_os = "macos"))]
 pub type ChannelItem = Frame;
 
+/// Options honoured by the platform's native engine
+const NATIVE_CAPABILITIES: Capabilities = Capabilities {
+    target: cfg!(any(target_os = "macos", target_os = "windows")),
+    audio: cfg!(any(target_os = "macos", target_os = "windows")),
+    exclude_process_audio: false,
+    crop: cfg!(any(target_os = "macos", target_os = "windows")),
+    output_type: cfg!(any(target_os = "macos", target_os = "windows")),
+    scaling: cfg!(any(target_os = "macos", target_os = "windows")),
+    excluded_targets: cfg!(target_os = "macos"),
+    cursor: true,
+    cursor_metadata: cfg!(target_os = "linux"),
+    highlight: cfg!(target_os = "windows"),
+    multi_source: cfg!(target_os = "linux"),
+    restore_session: cfg!(target_os = "linux"),
+    source_kinds: cfg!(target_os = "linux"),
+    input_devices: cfg!(target_os = "linux"),
+};
+
+/// Capabilities of the backend [`Capturer::build`](super::Capturer::build) picks for `options`
+pub fn capabilities(options: &Options) -> Capabilities {
+    match &options.target {
+        Some(Target::Synthetic(_)) => synthetic::CAPABILITIES,
+        _ => NATIVE_CAPABILITIES,
+    }
+}
+
 pub fn get_output_frame_size(options: &Options) -> [u32; 2] {
+    if let Some(Target::Synthetic(source)) = &options.target {
+        return [source.width, source.height];
+    }
+
     #[cfg(target_os = "macos")]
     {
         mac::get_output_frame_size(options)
@@ -31,15 +71,19 @@ pub fn get_output_frame_size(options: &Options) -> [u32; 2] {
         win::get_output_frame_size(options)
     }
 
+    // The size is only known once the user picked a source in the portal dialog, see
+    // `Capturer::get_output_frame_size`
     #[cfg(target_os = "linux")]
     {
-        // TODO: How to calculate this on Linux?
         return [0, 0];
     }
 }
 
+/// Capture backend for the platform's native screen capture API
 pub struct Engine {
+    #[cfg(not(target_os = "linux"))]
     options: Options,
+    pub(crate) rx: channel::Receiver<ChannelItem>,
 
     #[cfg(target_os = "macos")]
     mac: (
@@ -49,95 +93,197 @@ pub struct Engine {
     ),
     #[cfg(target_os = "macos")]
     error_flag: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    #[cfg(target_os = "macos")]
+    clock: clock::FrameClock,
 
     #[cfg(target_os = "windows")]
     win: win::WCStream,
 
     #[cfg(target_os = "linux")]
-    linux: linux::LinuxCapturer,
+    pub(crate) linux: linux::LinuxCapturer,
 }
 
+// ScreenCaptureKit streams and their handlers can be used from any thread
+#[cfg(target_os = "macos")]
+unsafe impl Send for Engine {}
+
 impl Engine {
-    pub fn new(options: &Options, tx: mpsc::Sender<ChannelItem>) -> Engine {
+    pub fn new(options: &Options) -> Result<Engine, CaptureError> {
+        let (tx, rx) = channel::with_policy(options.queue_policy);
+
         #[cfg(target_os = "macos")]
         {
             let error_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
-            let mac = mac::create_capturer(options, tx, error_flag.clone()).unwrap();
+            let mac = mac::create_capturer(options, tx, error_flag.clone())
+                .map_err(|e| CaptureError::Backend(e.to_string()))?;
 
-            Engine {
+            Ok(Engine {
                 mac,
                 error_flag,
+                clock: Default::default(),
                 options: (*options).clone(),
-            }
+                rx,
+            })
         }
 
         #[cfg(target_os = "windows")]
         {
-            let win = win::create_capturer(&options, tx).unwrap();
-            return Engine {
+            let win = win::create_capturer(&options, tx)
+                .map_err(|e| CaptureError::Backend(format!("{e:?}")))?;
+            return Ok(Engine {
                 win,
                 options: (*options).clone(),
-            };
+                rx,
+            });
         }
 
         #[cfg(target_os = "linux")]
         {
-            let linux = linux::create_capturer(&options, tx);
-            return Engine {
-                linux,
-                options: (*options).clone(),
-            };
+            let linux = linux::create_capturer(&options, tx)?;
+            return Ok(Engine { linux, rx });
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn process_channel_item(&self, data: ChannelItem) -> Option<Frame> {
+        #[cfg(target_os = "macos")]
+        {
+            mac::process_sample_buffer(data.0, data.1, self.options.output_type, &self.clock)
+        }
+        #[cfg(not(target_os = "macos"))]
+        return Some(data);
+    }
+}
+
+impl CaptureBackend for Engine {
+    fn start(&mut self) -> Result<(), CaptureError> {
+        // The channel is closed when the capture is stopped, accept frames again on a restart
+        self.rx.reopen();
+
         #[cfg(target_os = "macos")]
         {
             use futures::executor::block_on;
 
-            block_on(self.mac.2.start()).expect("Failed to start capture");
+            block_on(self.mac.2.start())
+                .map_err(|e| CaptureError::Backend(format!("Failed to start capture: {e:?}")))
         }
 
         #[cfg(target_os = "windows")]
         {
             self.win.start_capture();
+            return Ok(());
         }
 
         #[cfg(target_os = "linux")]
         {
-            self.linux.start_capture();
+            return self.linux.start_capture().map_err(CaptureError::from);
         }
     }
 
-    pub fn stop(&mut self) {
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        // Release a producer waiting for room in the queue, or stopping it would never return
+        self.rx.close();
+
         #[cfg(target_os = "macos")]
         {
             use futures::executor::block_on;
 
-            block_on(self.mac.2.stop()).expect("Failed to stop capture");
+            block_on(self.mac.2.stop())
+                .map_err(|e| CaptureError::Backend(format!("Failed to stop capture: {e:?}")))
         }
 
         #[cfg(target_os = "windows")]
         {
             self.win.stop_capture();
+            return Ok(());
         }
 
         #[cfg(target_os = "linux")]
         {
-            self.linux.stop_capture();
+            return self.linux.stop_capture().map_err(CaptureError::from);
         }
     }
 
-    pub fn get_output_frame_size(&mut self) -> [u32; 2] {
-        get_output_frame_size(&self.options)
+    fn poll_next_frame(&self, cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+        loop {
+            match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(item)) => {
+                    if let Some(frame) = self.process_channel_item(item) {
+                        return Poll::Ready(Some(frame));
+                    }
+                    // Item filtered, poll the next one
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 
-    pub fn process_channel_item(&self, data: ChannelItem) -> Option<Frame> {
+    fn output_frame_size(&self) -> [u32; 2] {
+        #[cfg(not(target_os = "linux"))]
+        {
+            get_output_frame_size(&self.options)
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            self.linux.output_frame_size()
+        }
+    }
+
+    fn wait_for_format(&self) -> Result<NegotiatedFormat, CaptureError> {
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(CaptureError::NotSupported)
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            Ok(self.linux.wait_for_format()?)
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        NATIVE_CAPABILITIES
+    }
+
+    /// Returns the error that terminated the capture, if the engine knows about one
+    fn error(&self) -> Option<CaptureError> {
         #[cfg(target_os = "macos")]
         {
-            mac::process_sample_buffer(data.0, data.1, self.options.output_type)
+            self.error_flag
+                .load(std::sync::atomic::Ordering::Relaxed)
+                .then(|| CaptureError::Backend(String::from("Screen capture stream stopped")))
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            return None;
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            return self.linux.error().map(CaptureError::from);
+        }
+    }
+
+    fn dropped_frames(&self) -> u64 {
+        self.rx.dropped()
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        #[cfg(target_os = "macos")]
+        {
+            PoolStats::default()
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            return self.win.pool_stats();
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            return self.linux.pool_stats();
         }
-        #[cfg(not(target_os = "macos"))]
-        return Some(data);
     }
 }
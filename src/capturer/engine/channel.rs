@@ -0,0 +1,458 @@
+//! Channel carrying captured items from the platform engines to the consumer.
+//!
+//! It behaves like [`std::sync::mpsc`], but the receiving side can also be polled from an
+//! async task, which gets woken as soon as an engine delivers a new item. A [`QueuePolicy`]
+//! decides what happens when the consumer falls behind.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+        mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::task::AtomicWaker;
+
+use crate::frame::Frame;
+
+/// How the capture channel behaves when the consumer does not keep up with the engine.
+///
+/// Capacities count the items the policy may drop, which are video frames. Audio is always
+/// queued and never dropped, since a gap in it is audible.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Queue every item, memory usage grows until the consumer catches up
+    #[default]
+    Unbounded,
+    /// Queue up to `n` items, then make the engine wait until the consumer takes one
+    Block(usize),
+    /// Queue up to `n` items, then drop the oldest queued item to make room for the new one
+    DropOldest(usize),
+    /// Queue up to `n` items, then drop new items until the consumer takes one
+    DropNewest(usize),
+    /// Only keep the most recent item
+    Latest,
+}
+
+/// Items carried by the capture channel
+pub trait QueueItem {
+    /// Whether a [`QueuePolicy`] may drop this item when the consumer falls behind
+    fn droppable(&self) -> bool;
+}
+
+impl QueueItem for Frame {
+    fn droppable(&self) -> bool {
+        matches!(self, Frame::Video(_))
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl QueueItem
+    for (
+        cidre::arc::R<cidre::cm::SampleBuf>,
+        cidre::sc::stream::OutputType,
+    )
+{
+    fn droppable(&self) -> bool {
+        matches!(self.1, cidre::sc::stream::OutputType::Screen)
+    }
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool,
+    closed: bool,
+    dropped: u64,
+}
+
+impl<T: QueueItem> State<T> {
+    fn droppable_len(&self) -> usize {
+        self.items.iter().filter(|item| item.droppable()).count()
+    }
+
+    /// Drops the oldest droppable item, returns false if there is none
+    fn drop_oldest(&mut self) -> bool {
+        match self.items.iter().position(QueueItem::droppable) {
+            Some(index) => {
+                self.items.remove(index);
+                self.dropped += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    policy: QueuePolicy,
+    available: Condvar,
+    space: Condvar,
+    waker: AtomicWaker,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn notify(&self) {
+        self.available.notify_all();
+        self.waker.wake();
+    }
+
+    fn pop(&self) -> (Option<T>, MutexGuard<'_, State<T>>) {
+        let mut state = self.lock();
+        let item = state.items.pop_front();
+        if item.is_some() {
+            self.space.notify_one();
+        }
+        (item, state)
+    }
+}
+
+/// Creates a new unbounded channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    with_policy(QueuePolicy::Unbounded)
+}
+
+/// Creates a new channel that applies `policy` when the consumer falls behind.
+///
+/// Capacities of zero are treated as one.
+pub fn with_policy<T>(policy: QueuePolicy) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::new(),
+            senders: 1,
+            receiver_alive: true,
+            closed: false,
+            dropped: 0,
+        }),
+        policy,
+        available: Condvar::new(),
+        space: Condvar::new(),
+        waker: AtomicWaker::new(),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Sending half of a [`channel`], held by the capture engines
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: QueueItem> Sender<T> {
+    /// Queues an item according to the channel's [`QueuePolicy`] and wakes the receiver.
+    ///
+    /// Items dropped by the policy still count as sent. Fails if the receiver has been dropped
+    /// or closed, including while waiting for room in a full queue.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver_alive || state.closed {
+            return Err(SendError(item));
+        }
+
+        match self.shared.policy {
+            QueuePolicy::Unbounded => {}
+            _ if !item.droppable() => {}
+            QueuePolicy::Block(capacity) => {
+                while state.droppable_len() >= capacity.max(1)
+                    && state.receiver_alive
+                    && !state.closed
+                {
+                    state = self
+                        .shared
+                        .space
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                if !state.receiver_alive || state.closed {
+                    return Err(SendError(item));
+                }
+            }
+            QueuePolicy::DropOldest(capacity) => {
+                while state.droppable_len() >= capacity.max(1) && state.drop_oldest() {}
+            }
+            QueuePolicy::DropNewest(capacity) => {
+                if state.droppable_len() >= capacity.max(1) {
+                    state.dropped += 1;
+                    return Ok(());
+                }
+            }
+            QueuePolicy::Latest => while state.drop_oldest() {},
+        }
+
+        state.items.push_back(item);
+        drop(state);
+
+        self.shared.notify();
+        Ok(())
+    }
+}
+
+impl<T> Sender<T> {
+    /// Whether the receiver closed the channel, which it does when the capture is stopped
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        let disconnected = state.senders == 0;
+        drop(state);
+
+        if disconnected {
+            self.shared.notify();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// Receiving half of a [`channel`], held by the capturer
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until an item is available or every sender has been dropped
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.shared.space.notify_one();
+                return Ok(item);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self
+                .shared
+                .available
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Like [`Receiver::recv`], but gives up after `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.shared.space.notify_one();
+                return Ok(item);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Returns the next item without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let (item, state) = self.shared.pop();
+        match item {
+            Some(item) => Ok(item),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Polls for the next item, registering the task to be woken when one arrives.
+    ///
+    /// Resolves to `None` once every sender has been dropped and the queue is drained.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        // Register before checking the queue so an item sent in between is not missed
+        self.shared.waker.register(cx.waker());
+
+        let (item, state) = self.shared.pop();
+        match item {
+            Some(item) => Poll::Ready(Some(item)),
+            None if state.senders == 0 => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Number of items the [`QueuePolicy`] has dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    /// Makes every further send fail, waking senders blocked on a full queue.
+    ///
+    /// Engines call this before tearing down their producers, so a producer waiting for room
+    /// cannot keep the stop from completing. Items already queued can still be received.
+    pub fn close(&self) {
+        self.shared.lock().closed = true;
+        self.shared.space.notify_all();
+    }
+
+    /// Accepts items again after [`Receiver::close`], engines call this when restarted
+    pub fn reopen(&self) {
+        self.shared.lock().closed = false;
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.items.clear();
+        drop(state);
+
+        // Wake up senders waiting for room in a full queue
+        self.shared.space.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl QueueItem for u32 {
+        fn droppable(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Item {
+        Video(u32),
+        Audio(u32),
+    }
+
+    impl QueueItem for Item {
+        fn droppable(&self) -> bool {
+            matches!(self, Item::Video(_))
+        }
+    }
+
+    fn drain(rx: &Receiver<u32>) -> Vec<u32> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_newest_items() {
+        let (tx, rx) = with_policy(QueuePolicy::DropOldest(2));
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(drain(&rx), vec![3, 4]);
+        assert_eq!(rx.dropped(), 3);
+    }
+
+    #[test]
+    fn drop_newest_keeps_oldest_items() {
+        let (tx, rx) = with_policy(QueuePolicy::DropNewest(2));
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(drain(&rx), vec![0, 1]);
+        assert_eq!(rx.dropped(), 3);
+    }
+
+    #[test]
+    fn latest_only_keeps_last_item() {
+        let (tx, rx) = with_policy(QueuePolicy::Latest);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(drain(&rx), vec![4]);
+        assert_eq!(rx.dropped(), 4);
+    }
+
+    #[test]
+    fn block_waits_for_consumer() {
+        let (tx, rx) = with_policy(QueuePolicy::Block(1));
+        let producer = std::thread::spawn(move || {
+            for i in 0..5 {
+                tx.send(i).unwrap();
+            }
+        });
+        let received: Vec<u32> = std::iter::from_fn(|| rx.recv().ok()).collect();
+        producer.join().unwrap();
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[test]
+    fn policies_never_drop_audio() {
+        let (tx, rx) = with_policy(QueuePolicy::Latest);
+        tx.send(Item::Video(0)).unwrap();
+        tx.send(Item::Audio(1)).unwrap();
+        tx.send(Item::Video(2)).unwrap();
+        tx.send(Item::Audio(3)).unwrap();
+        let received: Vec<Item> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![Item::Audio(1), Item::Video(2), Item::Audio(3)]
+        );
+        assert_eq!(rx.dropped(), 1);
+    }
+
+    #[test]
+    fn close_releases_blocked_producer() {
+        let (tx, rx) = with_policy(QueuePolicy::Block(1));
+        let producer = std::thread::spawn(move || {
+            let mut sent = 0;
+            while tx.send(sent).is_ok() {
+                sent += 1;
+            }
+            sent
+        });
+        // Let the producer fill the queue and park on it
+        std::thread::sleep(Duration::from_millis(50));
+        rx.close();
+        assert_eq!(producer.join().unwrap(), 1);
+        assert_eq!(rx.recv(), Ok(0));
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn reopen_accepts_items_again() {
+        let (tx, rx) = channel();
+        rx.close();
+        assert!(tx.send(0).is_err());
+        rx.reopen();
+        tx.send(1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+}
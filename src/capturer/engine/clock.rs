@@ -0,0 +1,120 @@
+//! Timing of captured frames.
+//!
+//! Every platform reports capture times on its own monotonic clock. [`FrameClock`] turns them
+//! into presentation timestamps relative to the first frame of a capture, shared by the audio
+//! and video of that capture so they stay in sync, and [`to_system_time`] maps them onto the
+//! wall clock.
+
+use std::{
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Assigns presentation timestamps and sequence numbers to the frames of one capture
+#[derive(Debug, Default)]
+pub(crate) struct FrameClock {
+    origin: Arc<OnceLock<Duration>>,
+    video_sequence: AtomicU64,
+    audio_sequence: AtomicU64,
+}
+
+impl FrameClock {
+    /// Returns the pts and sequence number of a video frame captured at `source_time`
+    pub(crate) fn video(&self, source_time: Duration) -> (Duration, u64) {
+        (
+            self.pts(source_time),
+            self.video_sequence.fetch_add(1, Ordering::Relaxed),
+        )
+    }
+
+    /// Returns the pts and sequence number of an audio frame captured at `source_time`
+    pub(crate) fn audio(&self, source_time: Duration) -> (Duration, u64) {
+        (
+            self.pts(source_time),
+            self.audio_sequence.fetch_add(1, Ordering::Relaxed),
+        )
+    }
+
+    /// Clock for another source of the same capture, sharing the origin so the pts of both
+    /// line up, but numbering its frames separately
+    #[cfg(target_os = "linux")]
+    pub(crate) fn sibling(&self) -> FrameClock {
+        FrameClock {
+            origin: self.origin.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn pts(&self, source_time: Duration) -> Duration {
+        source_time.saturating_sub(*self.origin.get_or_init(|| source_time))
+    }
+}
+
+/// Maps `source_time` onto the wall clock, given that the source clock read `source_now` when
+/// the wall clock read `now`
+pub(crate) fn to_system_time(
+    source_time: Duration,
+    source_now: Duration,
+    now: SystemTime,
+) -> SystemTime {
+    if source_time <= source_now {
+        now.checked_sub(source_now - source_time).unwrap_or(now)
+    } else {
+        now.checked_add(source_time - source_now).unwrap_or(now)
+    }
+}
+
+/// Current time of `CLOCK_MONOTONIC`, the clock PipeWire timestamps buffers with
+#[cfg(target_os = "linux")]
+pub(crate) fn monotonic_now() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pts_starts_at_first_frame() {
+        let clock = FrameClock::default();
+        let second = Duration::from_secs(1);
+        assert_eq!(clock.video(100 * second), (Duration::ZERO, 0));
+        assert_eq!(clock.audio(101 * second), (second, 0));
+        assert_eq!(clock.video(102 * second), (2 * second, 1));
+        // Audio captured just before the first video frame is clamped to the start
+        assert_eq!(clock.audio(99 * second), (Duration::ZERO, 1));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn siblings_share_origin() {
+        let clock = FrameClock::default();
+        let sibling = clock.sibling();
+        let second = Duration::from_secs(1);
+        assert_eq!(clock.video(10 * second), (Duration::ZERO, 0));
+        assert_eq!(clock.video(11 * second), (second, 1));
+        assert_eq!(sibling.video(12 * second), (2 * second, 0));
+    }
+
+    #[test]
+    fn maps_source_time_to_wall_clock() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let source_now = Duration::from_secs(50);
+        assert_eq!(
+            to_system_time(Duration::from_secs(48), source_now, now),
+            now - Duration::from_secs(2)
+        );
+        assert_eq!(
+            to_system_time(Duration::from_secs(51), source_now, now),
+            now + Duration::from_secs(1)
+        );
+    }
+}
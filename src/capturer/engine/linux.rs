@@ -1,11 +1,12 @@
 use std::{
     mem::size_of,
     sync::{
-        atomic::{AtomicBool, AtomicU8},
-        mpsc::{self, SyncSender, sync_channel},
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{SendError, SyncSender, sync_channel},
     },
     thread::JoinHandle,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use pipewire as pw;
@@ -22,7 +23,10 @@ use pw::{
         },
         pod::{Pod, Property},
         sys::{
-            SPA_META_Header, SPA_PARAM_META_size, SPA_PARAM_META_type, spa_buffer, spa_meta_header,
+            SPA_CHUNK_FLAG_CORRUPTED, SPA_META_Cursor, SPA_META_Header, SPA_META_VideoDamage,
+            SPA_PARAM_META_size, SPA_PARAM_META_type, SPA_VIDEO_FORMAT_ABGR, SPA_VIDEO_FORMAT_ARGB,
+            SPA_VIDEO_FORMAT_BGRA, SPA_VIDEO_FORMAT_RGBA, spa_buffer, spa_meta, spa_meta_bitmap,
+            spa_meta_cursor, spa_meta_header, spa_meta_region,
         },
         utils::{Direction, SpaTypes},
     },
@@ -30,23 +34,74 @@ use pw::{
 };
 
 use crate::{
-    capturer::Options,
-    frame::{BGRxFrame, Frame, RGBFrame, RGBxFrame, VideoFrame, XBGRFrame},
+    capturer::{
+        Area, MultiSource, NegotiatedFormat, Options, PersistMode, Point, Size, SourceDrops,
+        SourceKind,
+    },
+    frame::{
+        BGRxFrame, CursorBitmap, CursorInfo, Frame, FramePool, PixelFormat, PoolStats, RGBFrame,
+        RGBxFrame, VideoFrame, XBGRFrame,
+    },
+    targets::{self, Display, PortalStream, Target, Window},
 };
 
+use self::composite::{Compositor, PixelLayout, Placement};
 pub(crate) use self::error::LinCapError;
 use self::portal::ScreenCastPortal;
+pub use self::remote_desktop::{Axis, RemoteDesktopSession};
+use self::session::SessionState;
+use super::{channel, clock};
 
+mod composite;
 mod error;
 mod portal;
+mod remote_desktop;
+mod session;
+
+/// Counts the frames lost on the PipeWire thread of a session
+#[derive(Debug, Default)]
+struct DropCounter {
+    out_of_buffers: AtomicU64,
+    corrupted: AtomicU64,
+}
+
+impl DropCounter {
+    fn out_of_buffers(&self) {
+        self.out_of_buffers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn corrupted(&self) {
+        self.corrupted.fetch_add(1, Ordering::Relaxed);
+    }
 
-static CAPTURER_STATE: AtomicU8 = AtomicU8::new(0);
-static STREAM_STATE_CHANGED_TO_ERROR: AtomicBool = AtomicBool::new(false);
+    fn get(&self) -> SourceDrops {
+        SourceDrops {
+            out_of_buffers: self.out_of_buffers.load(Ordering::Relaxed),
+            corrupted: self.corrupted.load(Ordering::Relaxed),
+        }
+    }
+}
 
 #[derive(Clone)]
 struct ListenerUserData {
-    pub tx: mpsc::Sender<Frame>,
+    pub tx: channel::Sender<Frame>,
     pub format: spa::param::video::VideoInfoRaw,
+    pub session: Arc<SessionState>,
+    pub pool: FramePool,
+    pub clock: Arc<clock::FrameClock>,
+    pub drops: Arc<DropCounter>,
+    /// Index of the portal stream this listener receives
+    pub source: usize,
+    pub compositor: Option<Arc<Mutex<Compositor>>>,
+}
+
+impl ListenerUserData {
+    /// Records why a frame could not be queued, unless the channel was closed to stop the capture
+    fn report_send_error(&self, err: SendError<Frame>) {
+        if !self.tx.is_closed() {
+            self.session.set_error(err.into());
+        }
+    }
 }
 
 fn param_changed_callback(
@@ -70,45 +125,287 @@ fn param_changed_callback(
         return;
     }
 
-    user_data
-        .format
-        .parse(param)
-        // TODO: Tell library user of the error
-        .expect("Failed to parse format parameter");
+    if let Err(e) = user_data.format.parse(param) {
+        user_data.session.set_error(LinCapError::Format(format!(
+            "failed to parse format parameter: {e}"
+        )));
+        return;
+    }
+
+    // Composite frames always show the whole virtual desktop, otherwise the first source
+    // stands for the format of the capture
+    let negotiated = match &user_data.compositor {
+        Some(compositor) => {
+            let compositor = compositor.lock().unwrap_or_else(PoisonError::into_inner);
+            Some(NegotiatedFormat {
+                pixel_format: PixelFormat::BGRx,
+                width: compositor.width() as u32,
+                height: compositor.height() as u32,
+            })
+        }
+        None if user_data.source == 0 => {
+            let size = user_data.format.size();
+            pixel_format(user_data.format.format()).map(|pixel_format| NegotiatedFormat {
+                pixel_format,
+                width: size.width,
+                height: size.height,
+            })
+        }
+        None => None,
+    };
+    if let Some(negotiated) = negotiated {
+        user_data.session.set_format(negotiated);
+    }
 }
 
 fn state_changed_callback(
     _stream: &StreamRef,
-    _user_data: &mut ListenerUserData,
+    user_data: &mut ListenerUserData,
     _old: StreamState,
     new: StreamState,
 ) {
     match new {
         StreamState::Error(e) => {
-            eprintln!("pipewire: State changed to error({e})");
-            STREAM_STATE_CHANGED_TO_ERROR.store(true, std::sync::atomic::Ordering::Relaxed);
+            user_data.session.set_error(LinCapError::PipeWire(format!(
+                "stream state changed to error({e})"
+            )));
         }
+        // The node went away, e.g. because the portal session was closed
+        StreamState::Unconnected => user_data.session.stop(),
         _ => {}
     }
 }
 
-unsafe fn get_timestamp(buffer: *mut spa_buffer) -> i64 {
-    let n_metas = (*buffer).n_metas;
-    if n_metas > 0 {
-        let mut meta_ptr = (*buffer).metas;
-        let metas_end = (*buffer).metas.wrapping_add(n_metas as usize);
-        while meta_ptr != metas_end {
-            if (*meta_ptr).type_ == SPA_META_Header {
-                let meta_header: &mut spa_meta_header =
-                    &mut *((*meta_ptr).data as *mut spa_meta_header);
-                return meta_header.pts;
-            }
-            meta_ptr = meta_ptr.wrapping_add(1);
-        }
-        0
+/// Most damage rectangles requested per buffer, producers merge anything beyond that
+const MAX_DAMAGE_REGIONS: usize = 16;
+
+/// Largest cursor image requested, in pixels per side
+const MAX_CURSOR_SIZE: usize = 256;
+
+/// Size of a cursor meta that fits a `side` x `side` cursor image
+const fn cursor_meta_size(side: usize) -> usize {
+    size_of::<spa_meta_cursor>() + size_of::<spa_meta_bitmap>() + side * side * 4
+}
+
+/// Finds the metadata of `type_` attached to `buffer`
+///
+/// # Safety
+///
+/// `buffer` must point to a valid `spa_buffer` that outlives `'a`.
+unsafe fn find_meta<'a>(buffer: *const spa_buffer, type_: u32) -> Option<&'a spa_meta> {
+    let buffer = unsafe { buffer.as_ref()? };
+    if buffer.metas.is_null() {
+        return None;
+    }
+    let metas = unsafe { std::slice::from_raw_parts(buffer.metas, buffer.n_metas as usize) };
+    metas
+        .iter()
+        .find(|meta| meta.type_ == type_ && !meta.data.is_null())
+}
+
+/// Reads the capture time of `buffer` on `CLOCK_MONOTONIC` from its `SPA_META_Header`
+///
+/// # Safety
+///
+/// `buffer` must point to a valid `spa_buffer`.
+unsafe fn get_timestamp(buffer: *const spa_buffer) -> Option<Duration> {
+    let meta = unsafe { find_meta(buffer, SPA_META_Header)? };
+    if (meta.size as usize) < size_of::<spa_meta_header>() {
+        return None;
+    }
+    let header = unsafe { &*(meta.data as *const spa_meta_header) };
+    u64::try_from(header.pts).ok().map(Duration::from_nanos)
+}
+
+/// Reads the `SPA_META_VideoDamage` rectangles of `buffer`.
+///
+/// The list ends at the first empty rectangle or at the end of the meta. Returns `None` if the
+/// producer didn't attach damage metadata to the buffer.
+///
+/// # Safety
+///
+/// `buffer` must point to a valid `spa_buffer`.
+unsafe fn damage_regions(buffer: *const spa_buffer) -> Option<Vec<Area>> {
+    let meta = unsafe { find_meta(buffer, SPA_META_VideoDamage)? };
+
+    let len = meta.size as usize / size_of::<spa_meta_region>();
+    let regions = unsafe { std::slice::from_raw_parts(meta.data as *const spa_meta_region, len) };
+
+    Some(
+        regions
+            .iter()
+            .map(|meta_region| meta_region.region)
+            .take_while(|region| region.size.width > 0 && region.size.height > 0)
+            .map(|region| Area {
+                origin: Point {
+                    x: region.position.x as f64,
+                    y: region.position.y as f64,
+                },
+                size: Size {
+                    width: region.size.width as f64,
+                    height: region.size.height as f64,
+                },
+            })
+            .collect(),
+    )
+}
+
+/// Reads the `SPA_META_Cursor` metadata of `buffer`.
+///
+/// Returns `None` if the producer didn't attach cursor metadata or the cursor is not on the
+/// captured surface.
+///
+/// # Safety
+///
+/// `buffer` must point to a valid `spa_buffer`.
+unsafe fn cursor_info(buffer: *const spa_buffer) -> Option<CursorInfo> {
+    let meta = unsafe { find_meta(buffer, SPA_META_Cursor)? };
+    if (meta.size as usize) < size_of::<spa_meta_cursor>() {
+        return None;
+    }
+    let cursor = unsafe { &*(meta.data as *const spa_meta_cursor) };
+    if cursor.id == 0 {
+        return None;
+    }
+
+    Some(CursorInfo {
+        id: cursor.id,
+        position: Point {
+            x: cursor.position.x as f64,
+            y: cursor.position.y as f64,
+        },
+        hotspot: Point {
+            x: cursor.hotspot.x as f64,
+            y: cursor.hotspot.y as f64,
+        },
+        bitmap: unsafe { cursor_bitmap(meta, cursor) },
+    })
+}
+
+/// Copies the cursor image that follows `cursor` inside `meta` as BGRA.
+///
+/// Producers only attach the image when it changed. Images in formats without alpha or
+/// reaching past the end of the meta are skipped.
+///
+/// # Safety
+///
+/// `meta` must be a valid cursor meta and `cursor` must point to its data.
+unsafe fn cursor_bitmap(meta: &spa_meta, cursor: &spa_meta_cursor) -> Option<CursorBitmap> {
+    let meta_size = meta.size as usize;
+    let bitmap_offset = cursor.bitmap_offset as usize;
+    if bitmap_offset < size_of::<spa_meta_cursor>()
+        || bitmap_offset + size_of::<spa_meta_bitmap>() > meta_size
+    {
+        return None;
+    }
+    let bitmap = unsafe {
+        (meta.data as *const u8)
+            .add(bitmap_offset)
+            .cast::<spa_meta_bitmap>()
+            .read_unaligned()
+    };
+
+    // Position of the blue, green, red and alpha bytes in a source pixel
+    let swizzle = match bitmap.format {
+        SPA_VIDEO_FORMAT_BGRA => [0, 1, 2, 3],
+        SPA_VIDEO_FORMAT_RGBA => [2, 1, 0, 3],
+        SPA_VIDEO_FORMAT_ARGB => [3, 2, 1, 0],
+        SPA_VIDEO_FORMAT_ABGR => [1, 2, 3, 0],
+        _ => return None,
+    };
+    let width = bitmap.size.width as usize;
+    let height = bitmap.size.height as usize;
+    let row_len = width * 4;
+    let stride = if bitmap.stride > 0 {
+        bitmap.stride as usize
     } else {
-        0
+        row_len
+    };
+    if width == 0 || height == 0 || stride < row_len {
+        return None;
+    }
+
+    let start = bitmap_offset + bitmap.offset as usize;
+    let len = stride * (height - 1) + row_len;
+    if start.checked_add(len)? > meta_size {
+        return None;
+    }
+    let pixels = unsafe { std::slice::from_raw_parts((meta.data as *const u8).add(start), len) };
+
+    Some(CursorBitmap {
+        width: width as i32,
+        height: height as i32,
+        data: pixels
+            .chunks(stride)
+            .flat_map(|row| row[..row_len].chunks_exact(4))
+            .flat_map(|pixel| swizzle.map(|i| pixel[i]))
+            .collect(),
+    })
+}
+
+/// Valid bytes of the first data plane of a dequeued buffer
+struct ChunkData<'a> {
+    bytes: &'a [u8],
+    stride: i32,
+}
+
+/// Locates the frame inside the first data plane of `buffer`.
+///
+/// Only the `chunk.size` bytes starting at `chunk.offset` hold the frame, the rest of the
+/// mapping up to `maxsize` is unused. Returns `None` for buffers that are not mapped into
+/// memory or are flagged as corrupted.
+///
+/// # Safety
+///
+/// `buffer` must point to a valid `spa_buffer` whose mapped memory outlives `'a`.
+unsafe fn chunk_data<'a>(buffer: *const spa_buffer) -> Option<ChunkData<'a>> {
+    let buffer = unsafe { buffer.as_ref()? };
+    if buffer.n_datas < 1 {
+        return None;
+    }
+    let data = unsafe { buffer.datas.as_ref()? };
+    let chunk = unsafe { data.chunk.as_ref()? };
+    if data.data.is_null()
+        || data.maxsize == 0
+        || chunk.flags & SPA_CHUNK_FLAG_CORRUPTED as i32 != 0
+    {
+        return None;
     }
+
+    let offset = (chunk.offset % data.maxsize) as usize;
+    let size = (chunk.size as usize).min(data.maxsize as usize - offset);
+    let bytes = unsafe { std::slice::from_raw_parts((data.data as *const u8).add(offset), size) };
+
+    Some(ChunkData {
+        bytes,
+        stride: chunk.stride,
+    })
+}
+
+/// Frame variant the PipeWire format is delivered as
+fn pixel_format(format: VideoFormat) -> Option<PixelFormat> {
+    match format {
+        VideoFormat::RGBx => Some(PixelFormat::RGBx),
+        VideoFormat::RGB => Some(PixelFormat::RGB),
+        VideoFormat::xBGR => Some(PixelFormat::XBGR),
+        VideoFormat::BGRx => Some(PixelFormat::BGRx),
+        _ => None,
+    }
+}
+
+/// Position of the color channels in the pixels of the formats the compositor can draw
+fn pixel_layout(format: VideoFormat) -> Option<PixelLayout> {
+    let (bytes_per_pixel, bgr) = match format {
+        VideoFormat::BGRx => (4, [0, 1, 2]),
+        VideoFormat::RGBx => (4, [2, 1, 0]),
+        VideoFormat::xBGR => (4, [1, 2, 3]),
+        VideoFormat::RGB => (3, [2, 1, 0]),
+        _ => return None,
+    };
+    Some(PixelLayout {
+        bytes_per_pixel,
+        bgr,
+    })
 }
 
 fn process_callback(stream: &StreamRef, user_data: &mut ListenerUserData) {
@@ -116,95 +413,280 @@ fn process_callback(stream: &StreamRef, user_data: &mut ListenerUserData) {
     if !buffer.is_null() {
         'outside: {
             let buffer = unsafe { (*buffer).buffer };
-            if buffer.is_null() {
+            // Buffers that arrive while the format is negotiated before the start are skipped
+            if buffer.is_null() || user_data.session.is_idle() {
                 break 'outside;
             }
-            let timestamp = unsafe { get_timestamp(buffer) };
+            let now = clock::monotonic_now();
+            // Buffers without a header are timed by their arrival
+            let capture_time = unsafe { get_timestamp(buffer) }.unwrap_or(now);
+            let damage = unsafe { damage_regions(buffer) };
+            let cursor = unsafe { cursor_info(buffer) };
 
-            let n_datas = unsafe { (*buffer).n_datas };
-            if n_datas < 1 {
-                return;
-            }
+            let Some(chunk) = (unsafe { chunk_data(buffer) }) else {
+                user_data.drops.corrupted();
+                break 'outside;
+            };
+
+            let format = user_data.format.format();
             let frame_size = user_data.format.size();
-            let frame_data: Vec<u8> = unsafe {
-                std::slice::from_raw_parts(
-                    (*(*buffer).datas).data as *mut u8,
-                    (*(*buffer).datas).maxsize as usize,
-                )
-                .to_vec()
+            let width = frame_size.width as i32;
+            let height = frame_size.height as i32;
+            let bytes_per_pixel = match format {
+                VideoFormat::RGB => 3,
+                _ => 4,
+            };
+            // A stride of 0 means the producer didn't set it, rows are tightly packed then
+            let stride = if chunk.stride > 0 {
+                chunk.stride
+            } else {
+                width * bytes_per_pixel
             };
+            let len = chunk.bytes.len().min(stride as usize * height as usize);
+            let display_time = clock::to_system_time(capture_time, now, SystemTime::now());
+            let (pts, sequence) = user_data.clock.video(capture_time);
 
-            if let Err(e) = match user_data.format.format() {
+            if let Some(compositor) = &user_data.compositor {
+                let Some(layout) = pixel_layout(format) else {
+                    user_data.session.set_error(LinCapError::Format(format!(
+                        "unsupported frame format received: {format:?}"
+                    )));
+                    break 'outside;
+                };
+                let mut compositor = compositor.lock().unwrap_or_else(PoisonError::into_inner);
+                let (width, height) = (width as usize, height as usize);
+                let area = compositor.draw(
+                    user_data.source,
+                    &chunk.bytes[..len],
+                    stride as usize,
+                    width,
+                    height,
+                    layout,
+                );
+                let cursor = cursor.map(|cursor| CursorInfo {
+                    position: compositor.map_point(
+                        user_data.source,
+                        &cursor.position,
+                        width,
+                        height,
+                    ),
+                    ..cursor
+                });
+                let frame = BGRxFrame {
+                    display_time,
+                    pts,
+                    sequence,
+                    width: compositor.width() as i32,
+                    height: compositor.height() as i32,
+                    data: user_data.pool.copy_from_slice(compositor.canvas()),
+                    stride: compositor.width() as i32 * 4,
+                    damage: Some(area.into_iter().collect()),
+                    cursor,
+                    repeat: false,
+                    source: 0,
+                };
+                drop(compositor);
+                if let Err(e) = user_data.tx.send(Frame::Video(VideoFrame::BGRx(frame))) {
+                    user_data.report_send_error(e);
+                }
+                break 'outside;
+            }
+
+            let frame_data = user_data.pool.copy_from_slice(&chunk.bytes[..len]);
+
+            if let Err(e) = match format {
                 VideoFormat::RGBx => user_data.tx.send(Frame::Video(VideoFrame::RGBx(RGBxFrame {
-                    display_time: std::time::UNIX_EPOCH + std::time::Duration::from_nanos(timestamp as u64),
-                    width: frame_size.width as i32,
-                    height: frame_size.height as i32,
+                    display_time,
+                    pts,
+                    sequence,
+                    width,
+                    height,
                     data: frame_data,
+                    stride,
+                    damage,
+                    cursor,
+                    repeat: false,
+                    source: user_data.source,
                 }))),
                 VideoFormat::RGB => user_data.tx.send(Frame::Video(VideoFrame::RGB(RGBFrame {
-                    display_time: std::time::UNIX_EPOCH + std::time::Duration::from_nanos(timestamp as u64),
-                    width: frame_size.width as i32,
-                    height: frame_size.height as i32,
+                    display_time,
+                    pts,
+                    sequence,
+                    width,
+                    height,
                     data: frame_data,
+                    stride,
+                    damage,
+                    cursor,
+                    repeat: false,
+                    source: user_data.source,
                 }))),
                 VideoFormat::xBGR => user_data.tx.send(Frame::Video(VideoFrame::XBGR(XBGRFrame {
-                    display_time: std::time::UNIX_EPOCH + std::time::Duration::from_nanos(timestamp as u64),
-                    width: frame_size.width as i32,
-                    height: frame_size.height as i32,
+                    display_time,
+                    pts,
+                    sequence,
+                    width,
+                    height,
                     data: frame_data,
+                    stride,
+                    damage,
+                    cursor,
+                    repeat: false,
+                    source: user_data.source,
                 }))),
                 VideoFormat::BGRx => user_data.tx.send(Frame::Video(VideoFrame::BGRx(BGRxFrame {
-                    display_time: std::time::UNIX_EPOCH + std::time::Duration::from_nanos(timestamp as u64),
-                    width: frame_size.width as i32,
-                    height: frame_size.height as i32,
+                    display_time,
+                    pts,
+                    sequence,
+                    width,
+                    height,
                     data: frame_data,
+                    stride,
+                    damage,
+                    cursor,
+                    repeat: false,
+                    source: user_data.source,
                 }))),
-                _ => panic!("Unsupported frame format received"),
+                format => {
+                    user_data.session.set_error(LinCapError::Format(format!(
+                        "unsupported frame format received: {format:?}"
+                    )));
+                    Ok(())
+                }
             } {
-                eprintln!("{e}");
+                user_data.report_send_error(e);
             }
         }
     } else {
-        eprintln!("Out of buffers");
+        user_data.drops.out_of_buffers();
     }
 
     unsafe { stream.queue_raw_buffer(buffer) };
 }
 
+/// Serializes a `SPA_PARAM_Meta` param asking the producer to attach metadata of `type_`
+fn meta_param(type_: u32, size: pw::spa::pod::Value) -> Result<Vec<u8>, LinCapError> {
+    let obj = pw::spa::pod::object!(
+        SpaTypes::ObjectParamMeta,
+        ParamType::Meta,
+        Property::new(
+            SPA_PARAM_META_type,
+            pw::spa::pod::Value::Id(pw::spa::utils::Id(type_))
+        ),
+        Property::new(SPA_PARAM_META_size, size),
+    );
+
+    Ok(pw::spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(obj),
+    )?
+    .0
+    .into_inner())
+}
+
+/// Meta size the producer may pick between `min` and `max` bytes
+fn size_range(default: usize, min: usize, max: usize) -> pw::spa::pod::Value {
+    pw::spa::pod::Value::Choice(pw::spa::pod::ChoiceValue::Int(pw::spa::utils::Choice(
+        pw::spa::utils::ChoiceFlags::empty(),
+        pw::spa::utils::ChoiceEnum::Range {
+            default: default as i32,
+            min: min as i32,
+            max: max as i32,
+        },
+    )))
+}
+
+/// Where the sources are on the virtual desktop, `None` if the portal left any of it out
+fn placements(streams: &[portal::Stream]) -> Option<Vec<Placement>> {
+    streams
+        .iter()
+        .map(|stream| Some((stream.position()?, stream.size()?)))
+        .collect()
+}
+
+/// Bit of `kind` in the source type bitmask of the portal
+fn source_type(kind: SourceKind) -> u32 {
+    match kind {
+        SourceKind::Monitor => 1,
+        SourceKind::Window => 2,
+        SourceKind::Virtual => 4,
+    }
+}
+
+/// Source type bitmask of the portal for a set of kinds
+fn source_types(kinds: &[SourceKind]) -> u32 {
+    kinds
+        .iter()
+        .fold(0, |types, &kind| types | source_type(kind))
+}
+
+fn source_kind(source_type: u32) -> Option<SourceKind> {
+    match source_type {
+        1 => Some(SourceKind::Monitor),
+        2 => Some(SourceKind::Window),
+        4 => Some(SourceKind::Virtual),
+        _ => None,
+    }
+}
+
+/// Describes the source behind a portal stream as a target
+fn stream_target(stream: &portal::Stream) -> Target {
+    let node_id = stream.pw_node_id();
+    let raw_handle = PortalStream {
+        node_id,
+        id: stream.id().map(String::from),
+        position: stream.position(),
+        size: stream.size(),
+        source_type: stream.source_type().and_then(source_kind),
+        mapping_id: stream.mapping_id().map(String::from),
+    };
+    if raw_handle.source_type == Some(SourceKind::Window) {
+        Target::Window(Window {
+            id: node_id,
+            title: format!("Window {node_id}"),
+            raw_handle,
+        })
+    } else {
+        Target::Display(Display {
+            id: node_id,
+            title: format!("Display {node_id}"),
+            raw_handle,
+        })
+    }
+}
+
 // TODO: Format negotiation
 fn pipewire_capturer(
     options: Options,
-    tx: mpsc::Sender<Frame>,
+    tx: channel::Sender<Frame>,
     ready_sender: &SyncSender<bool>,
-    stream_id: u32,
+    portal_session: portal::ScreenCastSession,
+    session: Arc<SessionState>,
+    pool: FramePool,
+    drops: Arc<DropCounter>,
 ) -> Result<(), LinCapError> {
     pw::init();
 
     let mainloop = MainLoop::new(None)?;
     let context = Context::new(&mainloop)?;
-    let core = context.connect(None)?;
+    // The portal's remote only exposes the picked streams, but is reachable from sandboxes.
+    // The core owns the fd from now on and closes it when the capture ends
+    let core = context.connect_fd(portal_session.remote, None)?;
+    let streams = portal_session.streams;
 
-    let user_data = ListenerUserData {
-        tx,
-        format: Default::default(),
+    let compositor = if options.multi_source == MultiSource::Composite {
+        let compositor = placements(&streams)
+            .and_then(|placements| Compositor::new(&placements))
+            .ok_or_else(|| {
+                LinCapError::Portal(String::from(
+                    "Composite capture needs the position and size of every source",
+                ))
+            })?;
+        Some(Arc::new(Mutex::new(compositor)))
+    } else {
+        None
     };
-
-    let stream = pw::stream::Stream::new(
-        &core,
-        "sc-cap",
-        properties! {
-            *pw::keys::MEDIA_TYPE => "Video",
-            *pw::keys::MEDIA_CATEGORY => "Capture",
-        *pw::keys::MEDIA_ROLE => "Screen",
-    },
-)?;
-
-    let _listener = stream
-        .add_local_listener_with_user_data(user_data.clone())
-        .state_changed(state_changed_callback)
-        .param_changed(param_changed_callback)
-        .process(process_callback)
-        .register()?;
+    let frame_clock = Arc::new(clock::FrameClock::default());
 
     let obj = pw::spa::pod::object!(
         pw::spa::utils::SpaTypes::ObjectParamFormat,
@@ -252,129 +734,524 @@ fn pipewire_capturer(
         ),
     );
 
-    let metas_obj = pw::spa::pod::object!(
-        SpaTypes::ObjectParamMeta,
-        ParamType::Meta,
-        Property::new(
-            SPA_PARAM_META_type,
-            pw::spa::pod::Value::Id(pw::spa::utils::Id(SPA_META_Header))
-        ),
-        Property::new(
-            SPA_PARAM_META_size,
-            pw::spa::pod::Value::Int(size_of::<pw::spa::sys::spa_meta_header>() as i32)
-        ),
-    );
-
     let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
         std::io::Cursor::new(Vec::new()),
         &pw::spa::pod::Value::Object(obj),
     )?
     .0
     .into_inner();
-    let metas_values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
-        std::io::Cursor::new(Vec::new()),
-        &pw::spa::pod::Value::Object(metas_obj),
-    )?
-    .0
-    .into_inner();
+    let metas_values = meta_param(
+        SPA_META_Header,
+        pw::spa::pod::Value::Int(size_of::<spa_meta_header>() as i32),
+    )?;
+    let damage_values = meta_param(
+        SPA_META_VideoDamage,
+        size_range(
+            size_of::<spa_meta_region>() * MAX_DAMAGE_REGIONS,
+            size_of::<spa_meta_region>(),
+            size_of::<spa_meta_region>() * MAX_DAMAGE_REGIONS,
+        ),
+    )?;
+    let cursor_values = if options.cursor_metadata {
+        Some(meta_param(
+            SPA_META_Cursor,
+            size_range(
+                cursor_meta_size(64),
+                cursor_meta_size(1),
+                cursor_meta_size(MAX_CURSOR_SIZE),
+            ),
+        )?)
+    } else {
+        None
+    };
 
-    let mut params = [
+    let mut params = vec![
         pw::spa::pod::Pod::from_bytes(&values).unwrap(),
         pw::spa::pod::Pod::from_bytes(&metas_values).unwrap(),
+        pw::spa::pod::Pod::from_bytes(&damage_values).unwrap(),
     ];
+    if let Some(cursor_values) = &cursor_values {
+        params.push(pw::spa::pod::Pod::from_bytes(cursor_values).unwrap());
+    }
 
-    stream.connect(
-        Direction::Input,
-        Some(stream_id),
-        pw::stream::StreamFlags::AUTOCONNECT | pw::stream::StreamFlags::MAP_BUFFERS,
-        &mut params,
-    )?;
+    // One PipeWire stream per source, they have to stay alive as long as the loop runs
+    let mut connected = Vec::with_capacity(streams.len());
+    for (source, portal_stream) in streams.iter().enumerate() {
+        let user_data = ListenerUserData {
+            tx: tx.clone(),
+            format: Default::default(),
+            session: session.clone(),
+            pool: pool.clone(),
+            // Separate sources count their own frames, composited ones end up in one sequence
+            clock: if options.multi_source == MultiSource::Separate {
+                Arc::new(frame_clock.sibling())
+            } else {
+                frame_clock.clone()
+            },
+            drops: drops.clone(),
+            source,
+            compositor: compositor.clone(),
+        };
 
-    ready_sender.send(true)?;
+        let stream = pw::stream::Stream::new(
+            &core,
+            "sc-cap",
+            properties! {
+                *pw::keys::MEDIA_TYPE => "Video",
+                *pw::keys::MEDIA_CATEGORY => "Capture",
+                *pw::keys::MEDIA_ROLE => "Screen",
+            },
+        )?;
 
-    while CAPTURER_STATE.load(std::sync::atomic::Ordering::Relaxed) == 0 {
-        std::thread::sleep(Duration::from_millis(10));
+        let listener = stream
+            .add_local_listener_with_user_data(user_data)
+            .state_changed(state_changed_callback)
+            .param_changed(param_changed_callback)
+            .process(process_callback)
+            .register()?;
+
+        stream.connect(
+            Direction::Input,
+            Some(portal_stream.pw_node_id()),
+            pw::stream::StreamFlags::AUTOCONNECT | pw::stream::StreamFlags::MAP_BUFFERS,
+            &mut params,
+        )?;
+        connected.push((stream, listener));
     }
 
+    ready_sender.send(true)?;
+
     let pw_loop = mainloop.loop_();
 
-    // User has called Capturer::start() and we start the main loop
-    while CAPTURER_STATE.load(std::sync::atomic::Ordering::Relaxed) == 1
-        && /* If the stream state got changed to `Error`, we exit. TODO: tell user that we exited */
-          !STREAM_STATE_CHANGED_TO_ERROR.load(std::sync::atomic::Ordering::Relaxed)
-    {
-        pw_loop.iterate(Duration::from_millis(100));
+    // Negotiate the format right away, so it is known before the capture is started
+    while session.is_idle() {
+        pw_loop.iterate(Duration::from_millis(10));
     }
 
-    Ok(())
+    // Once the user has called Capturer::start() we run the main loop until the session is
+    // stopped or the stream fails
+    session.run(|| {
+        pw_loop.iterate(Duration::from_millis(100));
+    })
 }
 
 pub struct LinuxCapturer {
     capturer_join_handle: Option<JoinHandle<Result<(), LinCapError>>>,
+    session: Arc<SessionState>,
+    pool: FramePool,
+    drops: Arc<DropCounter>,
+    restore_token: Option<String>,
+    targets: Vec<Target>,
+    /// Size of the frames according to the portal, until PipeWire negotiated the real one
+    portal_size: Option<(usize, usize)>,
+    remote_desktop: Option<Arc<RemoteDesktopSession>>,
+    /// Portal session of the capture, until it is closed
+    session_handle: Option<dbus::Path<'static>>,
     // The pipewire stream is deleted when the connection is dropped.
     // That's why we keep it alive
-    _connection: dbus::blocking::Connection,
+    connection: Arc<Mutex<dbus::blocking::Connection>>,
 }
 
 impl LinuxCapturer {
-    /// Fallible constructor that returns a LinuxCapturer or a LinCapError instead of panicking.
-    pub fn try_new(options: &Options, tx: mpsc::Sender<Frame>) -> Result<Self, LinCapError> {
+    pub fn new(options: &Options, tx: channel::Sender<Frame>) -> Result<Self, LinCapError> {
+        if options
+            .target
+            .as_ref()
+            .is_some_and(|target| !targets::is_user_selection(target))
+        {
+            return Err(LinCapError::Portal(String::from(
+                "Sources can only be picked in the portal dialog, not passed as a target",
+            )));
+        }
+
         let connection = dbus::blocking::Connection::new_session()?;
         let portal = ScreenCastPortal::new(&connection);
-        let portal = portal.show_cursor(options.show_cursor)?;
-        let stream = portal.create_stream()?;
-        let stream_id = stream.pw_node_id();
+        let portal = if options.cursor_metadata {
+            portal.cursor_metadata()?
+        } else {
+            portal.show_cursor(options.show_cursor)?
+        };
+        let persist_mode = match options.persist_mode {
+            PersistMode::DoNot => 0,
+            PersistMode::Application => 1,
+            PersistMode::Persistent => 2,
+        };
+        let portal = match options.portal_timeout {
+            Some(timeout) => portal.timeout(timeout),
+            None => portal,
+        };
+        let portal = match &options.parent_window {
+            Some(parent_window) => portal.parent_window(parent_window.to_string()),
+            None => portal,
+        };
+        let mut portal_session = portal
+            .cancel_on(options.portal_cancel.flag())
+            .multiple(options.multi_source != MultiSource::Single)
+            .remote_desktop(remote_desktop::device_types(&options.input_devices))?
+            .persist(persist_mode, options.restore_token.clone())?
+            .source_types(source_types(&options.source_kinds))?
+            .start_session()?;
+        let restore_token = portal_session.restore_token.take();
+        if options.multi_source == MultiSource::Single {
+            portal_session.streams.truncate(1);
+        }
+        let streams = &portal_session.streams;
+        let targets: Vec<Target> = streams.iter().map(stream_target).collect();
+        let portal_size = if options.multi_source == MultiSource::Composite {
+            placements(streams).and_then(|placements| Compositor::canvas_size(&placements))
+        } else {
+            streams[0]
+                .size()
+                .map(|(width, height)| (width as usize, height as usize))
+        };
+        crate::targets::remember_portal_targets(&targets);
+
+        // Input goes through the connection that owns the session
+        let connection = Arc::new(Mutex::new(connection));
+        let remote_desktop = (portal_session.devices != 0).then(|| {
+            Arc::new(RemoteDesktopSession::new(
+                connection.clone(),
+                portal_session.session_handle.clone(),
+                portal_session.devices,
+                &portal_session.streams,
+                options.multi_source == MultiSource::Composite,
+            ))
+        });
 
         // TODO: Fix this hack
         let options = options.clone();
+        let session = Arc::new(SessionState::default());
+        let session_handle = portal_session.session_handle.clone();
+        // Closing the session from the shell ends the stream like stop_capture does
+        portal::on_session_closed(
+            &connection.lock().unwrap_or_else(PoisonError::into_inner),
+            session_handle.clone(),
+            {
+                let session = session.clone();
+                move || session.stop()
+            },
+        )?;
+        watch_portal(connection.clone(), session.clone());
+        let pool = FramePool::new(options.frame_pool_size);
+        let drops = Arc::new(DropCounter::default());
         let (ready_sender, ready_recv) = sync_channel(1);
-        let capturer_join_handle = std::thread::spawn(move || {
-            let res = pipewire_capturer(options, tx, &ready_sender, stream_id);
-            if let Err(ref err) = res {
-                ready_sender.send(false)?;
-            } else {
+        let capturer_join_handle = std::thread::spawn({
+            let session = session.clone();
+            let pool = pool.clone();
+            let drops = drops.clone();
+            move || {
+                let res = pipewire_capturer(
+                    options,
+                    tx,
+                    &ready_sender,
+                    portal_session,
+                    session,
+                    pool,
+                    drops,
+                );
+                if res.is_err() {
+                    let _ = ready_sender.try_send(false);
+                }
+                res
             }
-            res
         });
 
-        if !ready_recv.recv().map_err(|_| LinCapError::new("Failed to receive".into()))? {
-            return Err(LinCapError::new("Failed to setup capturer".into()));
+        if !ready_recv.recv().map_err(|_| LinCapError::Disconnected)? {
+            return Err(match capturer_join_handle.join() {
+                Ok(Err(err)) => err,
+                Ok(Ok(())) | Err(_) => {
+                    LinCapError::PipeWire(String::from("Failed to setup capturer"))
+                }
+            });
         }
 
         Ok(Self {
             capturer_join_handle: Some(capturer_join_handle),
-            _connection: connection,
+            session,
+            pool,
+            drops,
+            restore_token,
+            targets,
+            portal_size,
+            remote_desktop,
+            session_handle: Some(session_handle),
+            connection,
         })
     }
 
-    // Backwards-compatible infallible constructor used by the CPU engine. Prefer `try_new`.
-    pub fn new(options: &Options, tx: mpsc::Sender<Frame>) -> Self {
-        Self::try_new(options, tx).expect("Failed to initialize Linux capturer")
+    /// Returns the error that terminated the capture, if any
+    pub fn error(&self) -> Option<LinCapError> {
+        self.session.error()
     }
 
-    pub fn start_capture(&self) {
-        CAPTURER_STATE.store(1, std::sync::atomic::Ordering::Relaxed);
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
     }
 
-    pub fn stop_capture(&mut self) {
-        CAPTURER_STATE.store(2, std::sync::atomic::Ordering::Relaxed);
-        if let Some(handle) = self.capturer_join_handle.take() {
-            if let Err(e) = handle.join().expect("Failed to join capturer thread") {
-                eprintln!("Error occured capturing: {e}");
-            }
+    pub fn source_drops(&self) -> SourceDrops {
+        self.drops.get()
+    }
+
+    pub fn restore_token(&self) -> Option<String> {
+        self.restore_token.clone()
+    }
+
+    /// Size of the delivered frames, taken from the portal until the format is negotiated
+    pub fn output_frame_size(&self) -> [u32; 2] {
+        match (self.session.format(), self.portal_size) {
+            (Some(format), _) => [format.width, format.height],
+            (None, Some((width, height))) => [width as u32, height as u32],
+            (None, None) => [0, 0],
+        }
+    }
+
+    /// Blocks until PipeWire negotiated the format of the delivered frames
+    pub fn wait_for_format(&self) -> Result<NegotiatedFormat, LinCapError> {
+        self.session.wait_for_format()
+    }
+
+    /// Sources the user picked in the portal dialog, in the order of their source index
+    pub fn targets(&self) -> Vec<Target> {
+        self.targets.clone()
+    }
+
+    /// Input devices granted along with the sources, when they were requested
+    pub fn remote_desktop(&self) -> Option<Arc<RemoteDesktopSession>> {
+        self.remote_desktop.clone()
+    }
+
+    pub fn start_capture(&self) -> Result<(), LinCapError> {
+        if let Some(err) = self.error() {
+            return Err(err);
+        }
+        self.session.start();
+        Ok(())
+    }
+
+    pub fn stop_capture(&mut self) -> Result<(), LinCapError> {
+        self.session.stop();
+        let res = match self.capturer_join_handle.take() {
+            Some(handle) => handle.join().unwrap_or_else(|_| {
+                Err(LinCapError::PipeWire(String::from(
+                    "Capturer thread panicked",
+                )))
+            }),
+            None => Ok(()),
+        };
+        self.close_portal_session();
+        res
+    }
+
+    /// Ends the portal session, which takes down the sharing indicator of the shell
+    fn close_portal_session(&mut self) {
+        if let Some(session_handle) = self.session_handle.take() {
+            portal::close_session(
+                &self
+                    .connection
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner),
+                session_handle,
+            );
         }
-        CAPTURER_STATE.store(0, std::sync::atomic::Ordering::Relaxed);
-        STREAM_STATE_CHANGED_TO_ERROR.store(false, std::sync::atomic::Ordering::Relaxed);
     }
 }
 
-pub fn create_capturer(options: &Options, tx: mpsc::Sender<Frame>) -> LinuxCapturer {
-    LinuxCapturer::new(options, tx)
+impl Drop for LinuxCapturer {
+    fn drop(&mut self) {
+        // Let the PipeWire thread of this session exit even if the capture was never stopped
+        self.session.stop();
+        self.close_portal_session();
+    }
 }
 
-pub fn try_create_capturer(
+/// Processes the portal connection until the session ends, so the portal closing the session
+/// is noticed
+fn watch_portal(connection: Arc<Mutex<dbus::blocking::Connection>>, session: Arc<SessionState>) {
+    std::thread::spawn(move || {
+        while !session.is_stopped() {
+            // Only hold the connection briefly, input is sent through it as well
+            let processed = connection
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .process(Duration::ZERO);
+            if processed.is_err() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    });
+}
+
+pub fn create_capturer(
     options: &Options,
-    tx: mpsc::Sender<Frame>,
+    tx: channel::Sender<Frame>,
 ) -> Result<LinuxCapturer, LinCapError> {
-    LinuxCapturer::try_new(options, tx)
+    LinuxCapturer::new(options, tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::c_void;
+
+    use pw::spa::sys::{spa_chunk, spa_data};
+
+    use super::*;
+
+    /// Runs `f` with a single plane `spa_buffer` mapping `memory` and described by `chunk`
+    fn with_buffer<R>(
+        memory: &mut [u8],
+        mut chunk: spa_chunk,
+        f: impl FnOnce(*const spa_buffer) -> R,
+    ) -> R {
+        let mut data: spa_data = unsafe { std::mem::zeroed() };
+        data.maxsize = memory.len() as u32;
+        data.data = memory.as_mut_ptr() as *mut c_void;
+        data.chunk = &mut chunk;
+
+        let mut buffer: spa_buffer = unsafe { std::mem::zeroed() };
+        buffer.n_datas = 1;
+        buffer.datas = &mut data;
+
+        f(&buffer)
+    }
+
+    fn chunk(offset: u32, size: u32, stride: i32, flags: i32) -> spa_chunk {
+        let mut chunk: spa_chunk = unsafe { std::mem::zeroed() };
+        chunk.offset = offset;
+        chunk.size = size;
+        chunk.stride = stride;
+        chunk.flags = flags;
+        chunk
+    }
+
+    #[test]
+    fn chunk_data_honours_offset_size_and_stride() {
+        let mut memory: Vec<u8> = (0..32).collect();
+        with_buffer(&mut memory, chunk(4, 16, 8, 0), |buffer| {
+            let data = unsafe { chunk_data(buffer) }.unwrap();
+            assert_eq!(data.bytes, (4..20).collect::<Vec<u8>>());
+            assert_eq!(data.stride, 8);
+        });
+
+        // Offsets wrap around maxsize and sizes are clamped to the mapping
+        with_buffer(&mut memory, chunk(32 + 24, 64, 0, 0), |buffer| {
+            let data = unsafe { chunk_data(buffer) }.unwrap();
+            assert_eq!(data.bytes, (24..32).collect::<Vec<u8>>());
+        });
+    }
+
+    #[test]
+    fn chunk_data_skips_unusable_buffers() {
+        let mut memory = vec![0_u8; 16];
+        with_buffer(
+            &mut memory,
+            chunk(0, 16, 4, SPA_CHUNK_FLAG_CORRUPTED as i32),
+            |buffer| assert!(unsafe { chunk_data(buffer) }.is_none()),
+        );
+        with_buffer(&mut [], chunk(0, 16, 4, 0), |buffer| {
+            assert!(unsafe { chunk_data(buffer) }.is_none())
+        });
+    }
+
+    fn region(x: i32, y: i32, width: u32, height: u32) -> spa_meta_region {
+        let mut meta_region: spa_meta_region = unsafe { std::mem::zeroed() };
+        meta_region.region.position.x = x;
+        meta_region.region.position.y = y;
+        meta_region.region.size.width = width;
+        meta_region.region.size.height = height;
+        meta_region
+    }
+
+    /// Runs `f` with a `spa_buffer` carrying `regions` as its damage metadata
+    fn with_damage<R>(
+        regions: &mut [spa_meta_region],
+        f: impl FnOnce(*const spa_buffer) -> R,
+    ) -> R {
+        let mut meta: spa_meta = unsafe { std::mem::zeroed() };
+        meta.type_ = SPA_META_VideoDamage;
+        meta.size = size_of_val(regions) as u32;
+        meta.data = regions.as_mut_ptr() as *mut c_void;
+
+        let mut buffer: spa_buffer = unsafe { std::mem::zeroed() };
+        buffer.n_metas = 1;
+        buffer.metas = &mut meta;
+
+        f(&buffer)
+    }
+
+    #[test]
+    fn damage_regions_stop_at_empty_rectangle() {
+        let mut regions = [
+            region(0, 0, 10, 20),
+            region(5, 6, 7, 8),
+            region(0, 0, 0, 0),
+            region(1, 1, 1, 1),
+        ];
+        let damage = with_damage(&mut regions, |buffer| unsafe { damage_regions(buffer) }).unwrap();
+        assert_eq!(damage.len(), 2);
+        assert_eq!((damage[0].size.width, damage[0].size.height), (10.0, 20.0));
+        assert_eq!((damage[1].origin.x, damage[1].origin.y), (5.0, 6.0));
+        assert_eq!((damage[1].size.width, damage[1].size.height), (7.0, 8.0));
+
+        // No metadata means the damage is unknown, an empty list means nothing changed
+        let buffer: spa_buffer = unsafe { std::mem::zeroed() };
+        assert!(unsafe { damage_regions(&buffer) }.is_none());
+        let mut regions = [region(0, 0, 0, 0)];
+        let damage = with_damage(&mut regions, |buffer| unsafe { damage_regions(buffer) });
+        assert_eq!(damage.map(|damage| damage.len()), Some(0));
+    }
+
+    /// Cursor meta followed by its image, laid out the way producers fill it
+    #[repr(C)]
+    struct CursorMeta {
+        cursor: spa_meta_cursor,
+        bitmap: spa_meta_bitmap,
+        pixels: [u8; 16],
+    }
+
+    fn with_cursor<R>(meta: &mut CursorMeta, f: impl FnOnce(*const spa_buffer) -> R) -> R {
+        let mut spa_meta: spa_meta = unsafe { std::mem::zeroed() };
+        spa_meta.type_ = SPA_META_Cursor;
+        spa_meta.size = size_of::<CursorMeta>() as u32;
+        spa_meta.data = meta as *mut CursorMeta as *mut c_void;
+
+        let mut buffer: spa_buffer = unsafe { std::mem::zeroed() };
+        buffer.n_metas = 1;
+        buffer.metas = &mut spa_meta;
+
+        f(&buffer)
+    }
+
+    #[test]
+    fn cursor_info_converts_bitmap_to_bgra() {
+        let mut meta: CursorMeta = unsafe { std::mem::zeroed() };
+        meta.cursor.id = 7;
+        meta.cursor.position.x = 100;
+        meta.cursor.position.y = 50;
+        meta.cursor.hotspot.x = 1;
+        meta.cursor.bitmap_offset = std::mem::offset_of!(CursorMeta, bitmap) as u32;
+        meta.bitmap.format = SPA_VIDEO_FORMAT_RGBA;
+        meta.bitmap.size.width = 1;
+        meta.bitmap.size.height = 2;
+        // One pixel per row, padded to 8 bytes
+        meta.bitmap.stride = 8;
+        meta.bitmap.offset = size_of::<spa_meta_bitmap>() as u32;
+        meta.pixels = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0];
+
+        let cursor = with_cursor(&mut meta, |buffer| unsafe { cursor_info(buffer) }).unwrap();
+        assert_eq!(cursor.id, 7);
+        assert_eq!((cursor.position.x, cursor.position.y), (100.0, 50.0));
+        assert_eq!((cursor.hotspot.x, cursor.hotspot.y), (1.0, 0.0));
+        let bitmap = cursor.bitmap.unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (1, 2));
+        assert_eq!(bitmap.data, [3, 2, 1, 4, 7, 6, 5, 8]);
+
+        // Frames where the image didn't change only carry the position
+        meta.cursor.bitmap_offset = 0;
+        let cursor = with_cursor(&mut meta, |buffer| unsafe { cursor_info(buffer) }).unwrap();
+        assert!(cursor.bitmap.is_none());
+
+        meta.cursor.id = 0;
+        assert!(with_cursor(&mut meta, |buffer| unsafe { cursor_info(buffer) }).is_none());
+    }
 }
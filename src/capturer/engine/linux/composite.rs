@@ -0,0 +1,181 @@
+use crate::capturer::{Area, Point, Size};
+
+/// Where the color channels are inside a pixel of a source frame
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PixelLayout {
+    pub bytes_per_pixel: usize,
+    /// Offsets of the blue, green and red bytes
+    pub bgr: [usize; 3],
+}
+
+/// `(x, y)` position and `(width, height)` of a source on the virtual desktop
+pub(crate) type Placement = ((i32, i32), (i32, i32));
+
+/// Place of a source on the canvas
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Draws the frames of several monitors onto one BGRx canvas of the virtual desktop.
+///
+/// Every monitor keeps the last frame drawn for it, so the canvas always shows the latest
+/// state of all of them.
+#[derive(Debug)]
+pub(crate) struct Compositor {
+    rects: Vec<Rect>,
+    width: usize,
+    height: usize,
+    canvas: Vec<u8>,
+}
+
+impl Compositor {
+    /// Lays out sources with the given `(position, size)` on the virtual desktop
+    pub fn new(sources: &[Placement]) -> Option<Self> {
+        let (rects, width, height) = layout(sources)?;
+        Some(Self {
+            rects,
+            width,
+            height,
+            canvas: vec![0; width * height * 4],
+        })
+    }
+
+    /// `(width, height)` of the canvas for `sources`, without allocating it
+    pub fn canvas_size(sources: &[Placement]) -> Option<(usize, usize)> {
+        let (_, width, height) = layout(sources)?;
+        Some((width, height))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// BGRx pixels of the whole virtual desktop, rows are `width * 4` bytes
+    pub fn canvas(&self) -> &[u8] {
+        &self.canvas
+    }
+
+    /// Draws a `width` x `height` frame of `source` scaled into its place on the canvas.
+    ///
+    /// Returns the area of the canvas the frame covers.
+    pub fn draw(
+        &mut self,
+        source: usize,
+        data: &[u8],
+        stride: usize,
+        width: usize,
+        height: usize,
+        layout: PixelLayout,
+    ) -> Option<Area> {
+        let rect = *self.rects.get(source)?;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        for y in 0..rect.height {
+            let Some(row) = data.get(y * height / rect.height * stride..) else {
+                break;
+            };
+            let start = ((rect.y + y) * self.width + rect.x) * 4;
+            let canvas_row = &mut self.canvas[start..start + rect.width * 4];
+            for (x, pixel) in canvas_row.chunks_exact_mut(4).enumerate() {
+                let offset = x * width / rect.width * layout.bytes_per_pixel;
+                let Some(src) = row.get(offset..offset + layout.bytes_per_pixel) else {
+                    break;
+                };
+                pixel[0] = src[layout.bgr[0]];
+                pixel[1] = src[layout.bgr[1]];
+                pixel[2] = src[layout.bgr[2]];
+                pixel[3] = 255;
+            }
+        }
+
+        Some(Area {
+            origin: Point {
+                x: rect.x as f64,
+                y: rect.y as f64,
+            },
+            size: Size {
+                width: rect.width as f64,
+                height: rect.height as f64,
+            },
+        })
+    }
+
+    /// Maps a point in a `width` x `height` frame of `source` onto the canvas
+    pub fn map_point(&self, source: usize, point: &Point, width: usize, height: usize) -> Point {
+        let Some(rect) = self.rects.get(source) else {
+            return point.clone();
+        };
+        Point {
+            x: rect.x as f64 + point.x * rect.width as f64 / width.max(1) as f64,
+            y: rect.y as f64 + point.y * rect.height as f64 / height.max(1) as f64,
+        }
+    }
+}
+
+/// Places the sources relative to the top left corner of the virtual desktop and returns
+/// them with the size of the desktop
+fn layout(sources: &[Placement]) -> Option<(Vec<Rect>, usize, usize)> {
+    let left = sources.iter().map(|((x, _), _)| *x).min()?;
+    let top = sources.iter().map(|((_, y), _)| *y).min()?;
+    let rects: Vec<Rect> = sources
+        .iter()
+        .map(|((x, y), (width, height))| Rect {
+            x: (x - left) as usize,
+            y: (y - top) as usize,
+            width: (*width).max(0) as usize,
+            height: (*height).max(0) as usize,
+        })
+        .collect();
+    let width = rects.iter().map(|rect| rect.x + rect.width).max()?;
+    let height = rects.iter().map(|rect| rect.y + rect.height).max()?;
+    Some((rects, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGB: PixelLayout = PixelLayout {
+        bytes_per_pixel: 3,
+        bgr: [2, 1, 0],
+    };
+
+    #[test]
+    fn composites_monitors_at_their_position() {
+        // A 2x1 monitor left of a 1x1 monitor that sits one row lower
+        let sources = [((-2, 0), (2, 1)), ((0, 1), (1, 1))];
+        let mut compositor = Compositor::new(&sources).unwrap();
+        assert_eq!((compositor.width(), compositor.height()), (3, 2));
+        assert_eq!(Compositor::canvas_size(&sources), Some((3, 2)));
+
+        let area = compositor.draw(1, &[1, 2, 3], 3, 1, 1, RGB).unwrap();
+        assert_eq!((area.origin.x, area.origin.y), (2.0, 1.0));
+
+        // Frames larger than the logical size are scaled down, rows are `stride` apart
+        let left = [10, 11, 12, 20, 21, 22, 30, 31, 32, 40, 41, 42, 0, 0];
+        compositor.draw(0, &left, 14, 4, 1, RGB).unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(
+            compositor.canvas(),
+            [
+                12, 11, 10, 255,  32, 31, 30, 255,  0, 0, 0, 0,
+                0, 0, 0, 0,       0, 0, 0, 0,       3, 2, 1, 255,
+            ]
+        );
+        assert_eq!(
+            compositor.map_point(0, &Point { x: 2.0, y: 0.0 }, 4, 1).x,
+            1.0
+        );
+    }
+}
@@ -6,51 +6,85 @@ use std::{
 
 use pipewire::spa::pod::serialize::GenError;
 
-#[derive(Debug)]
-pub struct LinCapError {
-    msg: String,
+use crate::capturer::CaptureError;
+
+#[derive(Debug, Clone)]
+pub enum LinCapError {
+    /// The xdg-desktop-portal request failed or was rejected
+    Portal(String),
+    /// The user cancelled the portal dialog, or the request was cancelled through the
+    /// capture options
+    Cancelled,
+    /// The named portal request got no response in time
+    Timeout(String),
+    /// The portal ended the request without the user picking anything, e.g. because the
+    /// dialog was closed by the system
+    Ended,
+    /// PipeWire failed to set up or run the stream
+    PipeWire(String),
+    /// The negotiated format could not be parsed or is not supported
+    Format(String),
+    /// The capture thread went away
+    Disconnected,
 }
 
 impl Error for LinCapError {}
 
-impl LinCapError {
-    pub fn new(msg: String) -> Self {
-        Self { msg }
-    }
-}
-
 impl Display for LinCapError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", self.msg)
+        match self {
+            LinCapError::Portal(msg) => write!(f, "portal: {msg}"),
+            LinCapError::Cancelled => write!(f, "portal: request cancelled"),
+            LinCapError::Timeout(method) => write!(f, "portal: {method} timed out"),
+            LinCapError::Ended => write!(f, "portal: request ended without a result"),
+            LinCapError::PipeWire(msg) => write!(f, "pipewire: {msg}"),
+            LinCapError::Format(msg) => write!(f, "format: {msg}"),
+            LinCapError::Disconnected => write!(f, "capturer thread disconnected"),
+        }
     }
 }
 
 impl From<pipewire::Error> for LinCapError {
     fn from(e: pipewire::Error) -> Self {
-        Self::new(e.to_string())
+        Self::PipeWire(e.to_string())
     }
 }
 
-impl From<std::sync::mpsc::SendError<bool>> for LinCapError {
-    fn from(e: std::sync::mpsc::SendError<bool>) -> Self {
-        Self::new(e.to_string())
+impl<T> From<std::sync::mpsc::SendError<T>> for LinCapError {
+    fn from(_: std::sync::mpsc::SendError<T>) -> Self {
+        Self::Disconnected
     }
 }
 
 impl From<GenError> for LinCapError {
     fn from(e: GenError) -> Self {
-        Self::new(e.to_string())
+        Self::PipeWire(e.to_string())
     }
 }
 
 impl From<dbus::Error> for LinCapError {
     fn from(e: dbus::Error) -> Self {
-        Self::new(e.to_string())
+        Self::Portal(e.to_string())
     }
 }
 
+// A poisoned lock means the thread holding it panicked
 impl<T> From<PoisonError<T>> for LinCapError {
-    fn from(e: PoisonError<T>) -> Self {
-        Self::new(e.to_string())
+    fn from(_: PoisonError<T>) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<LinCapError> for CaptureError {
+    fn from(e: LinCapError) -> Self {
+        match e {
+            LinCapError::Portal(msg) => CaptureError::Portal(msg),
+            LinCapError::Cancelled => CaptureError::Cancelled,
+            LinCapError::Timeout(method) => CaptureError::PortalTimeout(method),
+            LinCapError::Ended => CaptureError::PortalEnded,
+            LinCapError::PipeWire(msg) => CaptureError::PipeWire(msg),
+            LinCapError::Format(msg) => CaptureError::Format(msg),
+            LinCapError::Disconnected => CaptureError::Disconnected,
+        }
     }
 }
@@ -1,16 +1,20 @@
 use std::{
-    sync::{Arc, Mutex, atomic::AtomicBool},
-    time::Duration,
+    os::fd::{FromRawFd, IntoRawFd, OwnedFd},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use dbus::{
-    arg::{self, PropMap, RefArg, Variant},
+    arg::{self, ArgType, PropMap, RefArg, Variant},
     blocking::{Connection, Proxy},
     message::MatchRule,
-    strings::{BusName, Interface},
+    strings::BusName,
 };
 
-use super::error::LinCapError;
+use super::{error::LinCapError, remote_desktop};
 
 // This code was autogenerated with `dbus-codegen-rust -d org.freedesktop.portal.Desktop -p /org/freedesktop/portal/desktop -f org.freedesktop.portal.ScreenCast`, see https://github.com/diwic/dbus-rs
 // {
@@ -159,10 +163,46 @@ impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target = T>> OrgFreed
 }
 // }
 
+// This code was autogenerated with `dbus-codegen-rust --file org.freedesktop.portal.Session.xml`, see https://github.com/diwic/dbus-rs
+// {
+trait OrgFreedesktopPortalSession {
+    fn close(&self) -> Result<(), dbus::Error>;
+}
+
+#[derive(Debug)]
+pub struct OrgFreedesktopPortalSessionClosed {
+    pub details: arg::PropMap,
+}
+
+impl arg::AppendAll for OrgFreedesktopPortalSessionClosed {
+    fn append(&self, i: &mut arg::IterAppend) {
+        arg::RefArg::append(&self.details, i);
+    }
+}
+
+impl arg::ReadAll for OrgFreedesktopPortalSessionClosed {
+    fn read(i: &mut arg::Iter) -> Result<Self, arg::TypeMismatchError> {
+        Ok(OrgFreedesktopPortalSessionClosed { details: i.read()? })
+    }
+}
+
+impl dbus::message::SignalArgs for OrgFreedesktopPortalSessionClosed {
+    const NAME: &'static str = "Closed";
+    const INTERFACE: &'static str = "org.freedesktop.portal.Session";
+}
+
+impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target = T>> OrgFreedesktopPortalSession
+    for blocking::Proxy<'a, C>
+{
+    fn close(&self) -> Result<(), dbus::Error> {
+        self.method_call("org.freedesktop.portal.Session", "Close", ())
+    }
+}
+// }
+
 type Response = Option<OrgFreedesktopPortalRequestResponse>;
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct StreamVardict {
     id: Option<String>,
     position: Option<(i32, i32)>,
@@ -179,49 +219,177 @@ impl Stream {
         self.0
     }
 
-    pub fn from_dbus(stream: &Variant<Box<dyn RefArg>>) -> Option<Self> {
-        let mut stream = stream.as_iter()?.next()?.as_iter()?;
-        let pipewire_node_id = stream.next()?.as_iter()?.next()?.as_u64()?;
+    /// Position of the source on the virtual desktop, only reported for monitors
+    pub fn position(&self) -> Option<(i32, i32)> {
+        self.1.position
+    }
 
-        // TODO: Get the rest of the properties
+    /// Size of the source in logical pixels
+    pub fn size(&self) -> Option<(i32, i32)> {
+        self.1.size
+    }
 
-        Some(Self(
-            pipewire_node_id as u32,
-            StreamVardict {
-                id: None,
-                position: None,
-                size: None,
-                source_type: None,
-                mapping_id: None,
-            },
-        ))
+    /// Identifies the stream within the session
+    pub fn id(&self) -> Option<&str> {
+        self.1.id.as_deref()
     }
-}
 
-macro_rules! match_response {
-    ( $code:expr ) => {
-        match $code {
-            0 => {}
-            1 => {
-                return Err(LinCapError::new(String::from(
-                    "User cancelled the interaction",
-                )));
-            }
-            2 => {
-                return Err(LinCapError::new(String::from(
-                    "The user interaction was ended in some other way",
-                )));
+    /// Bitmask value of the source type, 1 for a monitor, 2 for a window, 4 for a virtual one
+    pub fn source_type(&self) -> Option<u32> {
+        self.1.source_type
+    }
+
+    /// Identifier of the source in the RemoteDesktop portal's pointer regions
+    pub fn mapping_id(&self) -> Option<&str> {
+        self.1.mapping_id.as_deref()
+    }
+
+    /// Reads every stream of the `streams` result of `Start`, one per selected source
+    pub fn from_dbus(streams: &Variant<Box<dyn RefArg>>) -> Option<Vec<Self>> {
+        inner(&streams.0)?
+            .as_iter()?
+            .map(Self::from_entry)
+            .collect()
+    }
+
+    /// Reads a `(ua{sv})` stream entry
+    fn from_entry(entry: &dyn RefArg) -> Option<Self> {
+        let mut entry = inner(entry)?.as_iter()?;
+        let pipewire_node_id = inner(entry.next()?)?.as_u64()?;
+        let mut vardict = StreamVardict {
+            id: None,
+            position: None,
+            size: None,
+            source_type: None,
+            mapping_id: None,
+        };
+
+        if let Some(mut properties) = entry.next().and_then(|props| props.as_iter()) {
+            while let (Some(key), Some(value)) = (properties.next(), properties.next()) {
+                let string = || inner(value)?.as_str().map(String::from);
+                match key.as_str() {
+                    Some("id") => vardict.id = string(),
+                    Some("position") => vardict.position = pair(value),
+                    Some("size") => vardict.size = pair(value),
+                    Some("source_type") => {
+                        vardict.source_type =
+                            inner(value).and_then(|t| t.as_u64()).map(|t| t as u32);
+                    }
+                    Some("mapping_id") => vardict.mapping_id = string(),
+                    _ => {}
+                }
             }
-            _ => unreachable!(),
         }
-    };
+
+        Some(Self(pipewire_node_id as u32, vardict))
+    }
+}
+
+/// Looks through the variants wrapping `value`
+fn inner(value: &dyn RefArg) -> Option<&dyn RefArg> {
+    match value.arg_type() {
+        ArgType::Variant => inner(value.as_iter()?.next()?),
+        _ => Some(value),
+    }
 }
 
+/// Reads an `(ii)` stream property
+fn pair(value: &dyn RefArg) -> Option<(i32, i32)> {
+    let mut fields = inner(value)?.as_iter()?;
+    let x = inner(fields.next()?)?.as_i64()?;
+    let y = inner(fields.next()?)?.as_i64()?;
+    Some((x as i32, y as i32))
+}
+
+/// Sources the user granted in the dialog
+#[derive(Debug)]
+pub struct ScreenCastSession {
+    pub streams: Vec<Stream>,
+    /// Restores the sources in a later session, only handed out when a persist mode was set
+    pub restore_token: Option<String>,
+    /// PipeWire remote that exposes the streams, the only one reachable from a sandbox
+    pub remote: OwnedFd,
+    pub session_handle: dbus::Path<'static>,
+    /// Device type bitmask of the input devices the user granted, `0` without remote desktop
+    pub devices: u32,
+}
+
+/// Calls `on_closed` once the portal closes the session at `session_handle`, e.g. because the
+/// user stopped sharing from the shell. Signals only arrive while `connection` is processed
+pub fn on_session_closed(
+    connection: &Connection,
+    session_handle: dbus::Path<'static>,
+    mut on_closed: impl FnMut() + Send + 'static,
+) -> Result<(), LinCapError> {
+    let mut rule = MatchRule::new_signal("org.freedesktop.portal.Session", "Closed");
+    rule.path = Some(session_handle);
+    rule.sender = Some(BusName::from("org.freedesktop.portal.Desktop"));
+    connection.add_match(rule, move |_: OrgFreedesktopPortalSessionClosed, _, _| {
+        on_closed();
+        false
+    })?;
+    Ok(())
+}
+
+/// Ends the session at `session_handle` along with its streams
+pub fn close_session(connection: &Connection, session_handle: dbus::Path<'static>) {
+    // The portal may have closed the session already, then there is nothing left to close
+    let session = connection.with_proxy(
+        "org.freedesktop.portal.Desktop",
+        session_handle,
+        Duration::from_secs(5),
+    );
+    let _ = OrgFreedesktopPortalSession::close(&session);
+}
+
+/// Names the source types in the `types` bitmask, e.g. `monitor or window`
+fn source_type_names(types: u32) -> String {
+    [(1, "monitor"), (2, "window"), (4, "virtual")]
+        .into_iter()
+        .filter(|(bit, _)| types & bit != 0)
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+/// Path of the `Request` object the portal creates for `handle_token` on a connection named
+/// `unique_name`
+fn request_path(unique_name: &str, handle_token: &str) -> dbus::Path<'static> {
+    let sender = unique_name.trim_start_matches(':').replace('.', "_");
+    dbus::Path::from(format!(
+        "/org/freedesktop/portal/desktop/request/{sender}/{handle_token}"
+    ))
+}
+
+/// Results of a finished request, or how it ended without them
+fn response_results(response: OrgFreedesktopPortalRequestResponse) -> Result<PropMap, LinCapError> {
+    match response.response {
+        0 => Ok(response.results),
+        1 => Err(LinCapError::Cancelled),
+        _ => Err(LinCapError::Ended),
+    }
+}
+
+/// How long a request may take unless told otherwise, including the time the user spends in
+/// the dialog
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Longest a wait for a response goes without checking whether it was cancelled
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct ScreenCastPortal<'a> {
     proxy: Proxy<'a, &'a Connection>,
     request_token: String,
     session_token: String,
     cursor_mode: u32,
+    multiple: bool,
+    persist_mode: u32,
+    restore_token: Option<String>,
+    source_types: u32,
+    devices: u32,
+    parent_window: String,
+    timeout: Duration,
+    cancelled: Arc<AtomicBool>,
 }
 
 impl<'a> ScreenCastPortal<'a> {
@@ -240,6 +408,14 @@ impl<'a> ScreenCastPortal<'a> {
             request_token,
             session_token,
             cursor_mode: 1,
+            multiple: false,
+            persist_mode: 0,
+            restore_token: None,
+            source_types: 0,
+            devices: 0,
+            parent_window: String::new(),
+            timeout: DEFAULT_TIMEOUT,
+            cancelled: Arc::default(),
         }
     }
 
@@ -262,189 +438,372 @@ impl<'a> ScreenCastPortal<'a> {
             String::from("handle_token"),
             Variant(Box::new(self.request_token.clone())),
         );
-        // Attempt to query supported source types; if portal/backend is flaky, fall back to both
-        // monitors(1) | windows(2) = 3.
-        let types = self.proxy.available_source_types().unwrap_or(3);
+        // Unless restricted, attempt to query supported source types; if portal/backend is
+        // flaky, fall back to both monitors(1) | windows(2) = 3.
+        let types = match self.source_types {
+            0 => self.proxy.available_source_types().unwrap_or(3),
+            types => types,
+        };
         map.insert(String::from("types"), Variant(Box::new(types)));
-        map.insert(String::from("multiple"), Variant(Box::new(false)));
+        map.insert(String::from("multiple"), Variant(Box::new(self.multiple)));
         map.insert(
             String::from("cursor_mode"),
             Variant(Box::new(self.cursor_mode)),
         );
+        // A remote desktop session persists its sources along with the devices
+        if self.devices == 0 {
+            self.insert_persist_args(&mut map);
+        }
         Ok(map)
     }
 
-    fn handle_req_response(
+    fn select_devices_args(&self) -> arg::PropMap {
+        let mut map = arg::PropMap::new();
+        map.insert(
+            String::from("handle_token"),
+            Variant(Box::new(self.request_token.clone())),
+        );
+        map.insert(String::from("types"), Variant(Box::new(self.devices)));
+        self.insert_persist_args(&mut map);
+        map
+    }
+
+    fn insert_persist_args(&self, map: &mut arg::PropMap) {
+        if self.persist_mode != 0 {
+            map.insert(
+                String::from("persist_mode"),
+                Variant(Box::new(self.persist_mode)),
+            );
+        }
+        if let Some(token) = &self.restore_token {
+            map.insert(
+                String::from("restore_token"),
+                Variant(Box::new(token.clone())),
+            );
+        }
+    }
+
+    /// Sends a request with `call` and returns the results of its `Response` signal.
+    ///
+    /// The signal is subscribed to before the call, on the path the portal derives from the
+    /// handle token, so a response that comes back right away is not missed. A request that
+    /// is cancelled or gets no response in time is closed, which dismisses its dialog.
+    fn request(
+        &self,
+        method: &str,
+        call: impl FnOnce() -> Result<dbus::Path<'static>, dbus::Error>,
+    ) -> Result<PropMap, LinCapError> {
+        let connection = self.proxy.connection;
+        let response = Arc::new(Mutex::new(None));
+        let expected = request_path(&connection.unique_name(), &self.request_token);
+        let mut matches = vec![Self::watch_request(
+            connection,
+            expected.clone(),
+            &response,
+        )?];
+
+        let results = call()
+            .map_err(|e| {
+                LinCapError::Portal(format!("{method} failed via xdg-desktop-portal: {e}"))
+            })
+            .and_then(|request_handle| {
+                // Portals before version 0.9 did not derive the path from the handle token
+                if request_handle != expected {
+                    matches.push(Self::watch_request(
+                        connection,
+                        request_handle.clone(),
+                        &response,
+                    )?);
+                }
+                self.wait_for_response(method, &request_handle, &response)
+            });
+
+        for token in matches {
+            let _ = connection.remove_match(token);
+        }
+        results
+    }
+
+    /// Stores the `Response` signal of the request at `path` in `response`
+    fn watch_request(
         connection: &Connection,
         path: dbus::Path<'static>,
-        iterations: usize,
-        timeout: Duration,
-        response: Arc<Mutex<Response>>,
-    ) -> Result<(), dbus::Error> {
-        let got_response = Arc::new(AtomicBool::new(false));
-        let got_response_clone = Arc::clone(&got_response);
-
-        let mut rule = MatchRule::new();
-        rule.path = Some(dbus::Path::from(path));
-        rule.msg_type = Some(dbus::MessageType::Signal);
+        response: &Arc<Mutex<Response>>,
+    ) -> Result<dbus::channel::Token, LinCapError> {
+        let mut rule = MatchRule::new_signal("org.freedesktop.portal.Request", "Response");
+        rule.path = Some(path);
         rule.sender = Some(BusName::from("org.freedesktop.portal.Desktop"));
-        rule.interface = Some(Interface::from("org.freedesktop.portal.Request"));
-        let _match_guard = connection.add_match(
+        let response = Arc::clone(response);
+        let token = connection.add_match(
             rule,
-            move |res: OrgFreedesktopPortalRequestResponse, _chuh, _msg| {
-                let mut response = response.lock().expect("Failed to lock response mutex");
-                *response = Some(res);
-                got_response_clone.store(true, std::sync::atomic::Ordering::Relaxed);
-                false
+            move |res: OrgFreedesktopPortalRequestResponse, _, _| {
+                *response.lock().unwrap_or_else(PoisonError::into_inner) = Some(res);
+                // Keep the callback, the match is removed from the bus once the request is done
+                true
             },
         )?;
+        Ok(token)
+    }
 
-        for _ in 0..iterations {
-            connection.process(timeout)?;
+    /// Processes messages until the request at `request_handle` responded, was cancelled or
+    /// ran out of time
+    fn wait_for_response(
+        &self,
+        method: &str,
+        request_handle: &dbus::Path<'static>,
+        response: &Mutex<Response>,
+    ) -> Result<PropMap, LinCapError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(res) = response.lock()?.take() {
+                return response_results(res);
+            }
 
-            if got_response.load(std::sync::atomic::Ordering::Relaxed) {
-                break;
+            if self.cancelled.load(Ordering::Relaxed) {
+                self.close_request(request_handle);
+                return Err(LinCapError::Cancelled);
             }
-        }
 
-        Ok(())
-    }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                self.close_request(request_handle);
+                return Err(LinCapError::Timeout(String::from(method)));
+            }
 
-    fn create_session(&self) -> Result<dbus::Path<'_>, LinCapError> {
-        let request_handle = self
-            .proxy
-            .create_session(self.create_session_args())
-            .map_err(|e| LinCapError::new(format!(
-                "CreateSession failed via xdg-desktop-portal: {e}"
-            )))?;
+            self.proxy
+                .connection
+                .process(remaining.min(CANCEL_CHECK_INTERVAL))?;
+        }
+    }
 
-        let response = Arc::new(Mutex::new(None));
-        let response_clone = Arc::clone(&response);
-        Self::handle_req_response(
-            self.proxy.connection,
-            request_handle,
-            100,
-            Duration::from_millis(100),
-            response_clone,
-        )?;
+    /// Closes a pending request, the portal dismisses its dialog without a response
+    fn close_request(&self, request_handle: &dbus::Path<'static>) {
+        // The request may have finished in the meantime, then there is nothing left to close
+        let request = self.proxy.connection.with_proxy(
+            "org.freedesktop.portal.Desktop",
+            request_handle.clone(),
+            Duration::from_secs(5),
+        );
+        let _ = OrgFreedesktopPortalRequest::close(&request);
+    }
 
-        if let Some(res) = response.lock()?.take() {
-            match_response!(res.response);
-            match res
-                .results
-                .get("session_handle")
-                .map(|h| h.0.as_str().map(String::from))
-            {
-                Some(h) => {
-                    let p = dbus::Path::from(match h {
-                        Some(p) => p,
-                        None => {
-                            return Err(LinCapError::new(String::from(
-                                "Invalid session_handle received",
-                            )));
-                        }
-                    });
-
-                    return Ok(p);
-                }
-                None => return Err(LinCapError::new(String::from("Did not get session handle"))),
+    fn create_session(&self) -> Result<dbus::Path<'static>, LinCapError> {
+        let args = self.create_session_args();
+        let results = self.request("CreateSession", || match self.devices {
+            0 => self.proxy.create_session(args),
+            _ => {
+                remote_desktop::OrgFreedesktopPortalRemoteDesktop::create_session(&self.proxy, args)
             }
+        })?;
+
+        match results
+            .get("session_handle")
+            .map(|h| h.0.as_str().map(String::from))
+        {
+            Some(Some(handle)) => Ok(dbus::Path::from(handle)),
+            Some(None) => Err(LinCapError::Portal(String::from(
+                "Invalid session_handle received",
+            ))),
+            None => Err(LinCapError::Portal(String::from(
+                "Did not get session handle",
+            ))),
         }
-
-        Err(LinCapError::new(String::from("Did not get response")))
     }
 
     fn select_sources(&self, session_handle: dbus::Path) -> Result<(), LinCapError> {
-        let request_handle = self
-            .proxy
-            .select_sources(session_handle, self.select_sources_args()?)
-            .map_err(|e| LinCapError::new(format!(
-                "SelectSources failed via xdg-desktop-portal: {e}"
-            )))?;
-
-        let response = Arc::new(Mutex::new(None));
-        let response_clone = Arc::clone(&response);
-        Self::handle_req_response(
-            self.proxy.connection,
-            request_handle,
-            1200, // Wait 2 min
-            Duration::from_millis(100),
-            response_clone,
-        )?;
-
-        if let Some(res) = response.lock()?.take() {
-            match_response!(res.response);
-            return Ok(());
-        }
-
-        Err(LinCapError::new(String::from("Did not get response")))
+        let args = self.select_sources_args()?;
+        self.request("SelectSources", || {
+            self.proxy.select_sources(session_handle, args)
+        })?;
+        Ok(())
     }
 
-    fn start(&self, session_handle: dbus::Path) -> Result<Stream, LinCapError> {
-        let request_handle = self
-            .proxy
-            .start(
+    fn select_devices(&self, session_handle: dbus::Path) -> Result<(), LinCapError> {
+        let args = self.select_devices_args();
+        self.request("SelectDevices", || {
+            remote_desktop::OrgFreedesktopPortalRemoteDesktop::select_devices(
+                &self.proxy,
                 session_handle,
-                "",
-                {
-                    let mut opts = PropMap::new();
-                    opts.insert(
-                        String::from("handle_token"),
-                        Variant(Box::new(self.request_token.clone())),
-                    );
-                    opts
-                },
+                args,
             )
-            .map_err(|e| LinCapError::new(format!(
-                "Start failed via xdg-desktop-portal: {e}"
-            )))?;
-
-        let response = Arc::new(Mutex::new(None));
-        let response_clone = Arc::clone(&response);
-        Self::handle_req_response(
-            self.proxy.connection,
-            request_handle,
-            100, // Wait 10 s
-            Duration::from_millis(100),
-            response_clone,
-        )?;
-
-        if let Some(res) = response.lock()?.take() {
-            match_response!(res.response);
-            match res.results.get("streams") {
-                Some(s) => match Stream::from_dbus(s) {
-                    Some(s) => {
-                        return Ok(s);
-                    }
-                    None => {
-                        return Err(LinCapError::new(String::from(
-                            "Failed to extract stream properties",
-                        )));
-                    }
-                },
-                None => return Err(LinCapError::new(String::from("Did not get any streams"))),
-            }
-        }
+        })?;
+        Ok(())
+    }
 
-        Err(LinCapError::new(String::from("Did not get response")))
+    /// Returns the streams of the picked sources, the restore token and the granted devices
+    fn start(
+        &self,
+        session_handle: dbus::Path,
+    ) -> Result<(Vec<Stream>, Option<String>, u32), LinCapError> {
+        let mut opts = PropMap::new();
+        opts.insert(
+            String::from("handle_token"),
+            Variant(Box::new(self.request_token.clone())),
+        );
+        // A remote desktop session is started through its own portal, which starts the
+        // screencast along with it
+        let results = self.request("Start", || match self.devices {
+            0 => self.proxy.start(session_handle, &self.parent_window, opts),
+            _ => remote_desktop::OrgFreedesktopPortalRemoteDesktop::start(
+                &self.proxy,
+                session_handle,
+                &self.parent_window,
+                opts,
+            ),
+        })?;
+
+        let streams = match results.get("streams") {
+            Some(s) => match Stream::from_dbus(s) {
+                Some(s) if s.is_empty() => {
+                    return Err(LinCapError::Portal(String::from("Did not get any streams")));
+                }
+                Some(s) => s,
+                None => {
+                    return Err(LinCapError::Portal(String::from(
+                        "Failed to extract stream properties",
+                    )));
+                }
+            },
+            None => return Err(LinCapError::Portal(String::from("Did not get any streams"))),
+        };
+        let restore_token = results
+            .get("restore_token")
+            .and_then(|token| token.0.as_str())
+            .map(String::from);
+        let devices = results
+            .get("devices")
+            .and_then(|devices| devices.0.as_u64())
+            .map_or(0, |devices| devices as u32);
+
+        Ok((streams, restore_token, devices))
     }
 
-    pub fn open_remote(&self, session_handle: dbus::Path) -> Result<arg::OwnedFd, LinCapError> {
+    pub fn open_remote(&self, session_handle: dbus::Path) -> Result<OwnedFd, LinCapError> {
         let fd = self
             .proxy
             .open_pipe_wire_remote(session_handle, PropMap::new())
-            .map_err(|e| LinCapError::new(format!(
+            .map_err(|e| LinCapError::Portal(format!(
                 "OpenPipeWireRemote failed via xdg-desktop-portal: {e}"
             )))?;
-        Ok(fd)
+        // The message handed the fd over to us, so ownership moves on from dbus' wrapper
+        Ok(unsafe { OwnedFd::from_raw_fd(fd.into_raw_fd()) })
     }
 
-    pub fn create_stream(&self) -> Result<Stream, LinCapError> {
+    /// Lets the user pick the sources, and the input devices of a remote desktop session, and
+    /// returns a stream for each source
+    pub fn start_session(&self) -> Result<ScreenCastSession, LinCapError> {
         let session_handle = self.create_session()?;
-        // Follow the portal contract: select sources before requesting the PipeWire remote fd.
+        if self.devices != 0 {
+            self.select_devices(session_handle.clone())?;
+        }
         self.select_sources(session_handle.clone())?;
-        let _fd = self.open_remote(session_handle.clone())?;
-        self.start(session_handle)
+        let (streams, restore_token, devices) = self.start(session_handle.clone())?;
+        // Follow the portal contract: the remote only exposes the streams once they are started
+        let remote = self.open_remote(session_handle.clone())?;
+        Ok(ScreenCastSession {
+            streams,
+            restore_token,
+            remote,
+            session_handle,
+            devices,
+        })
+    }
+
+    /// Gives up on a request that got no response within `timeout`, which includes the time
+    /// the user spends in the dialog
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Makes the dialog modal to the window with the `x11:<xid>` or `wayland:<handle>`
+    /// identifier.
+    ///
+    /// The sources are picked in the dialog of `Start`, `SelectSources` takes no parent window.
+    pub fn parent_window(mut self, parent_window: String) -> Self {
+        self.parent_window = parent_window;
+        self
+    }
+
+    /// Closes the pending request once `cancelled` is set
+    pub fn cancel_on(mut self, cancelled: Arc<AtomicBool>) -> Self {
+        self.cancelled = cancelled;
+        self
+    }
+
+    /// Allows the user to pick more than one source in the dialog
+    pub fn multiple(mut self, multiple: bool) -> Self {
+        self.multiple = multiple;
+        self
+    }
+
+    /// Asks the portal to remember the sources with the given `SelectSources` persist mode
+    /// and restores the ones of `restore_token`.
+    ///
+    /// Both need version 4 of the ScreenCast portal, or version 2 of the RemoteDesktop portal
+    /// for a remote desktop session, older ones fail. Set up remote desktop before calling
+    /// this.
+    pub fn persist(
+        mut self,
+        persist_mode: u32,
+        restore_token: Option<String>,
+    ) -> Result<Self, LinCapError> {
+        if persist_mode == 0 && restore_token.is_none() {
+            return Ok(self);
+        }
+
+        let (portal, required, version) = match self.devices {
+            0 => ("ScreenCast", 4, self.proxy.version()?),
+            _ => (
+                "RemoteDesktop",
+                2,
+                remote_desktop::OrgFreedesktopPortalRemoteDesktop::version(&self.proxy)?,
+            ),
+        };
+        if version < required {
+            return Err(LinCapError::Portal(format!(
+                "Restoring sources needs version {required} of the {portal} portal, \
+                 version {version} is installed"
+            )));
+        }
+
+        self.persist_mode = persist_mode;
+        self.restore_token = restore_token;
+        Ok(self)
+    }
+
+    /// Runs a remote desktop session that also asks the user for control of the input
+    /// devices in the `devices` bitmask, `0` only captures
+    pub fn remote_desktop(mut self, devices: u32) -> Result<Self, LinCapError> {
+        if devices != 0 {
+            let available =
+                remote_desktop::OrgFreedesktopPortalRemoteDesktop::available_device_types(
+                    &self.proxy,
+                )?;
+            if devices & !available != 0 {
+                return Err(LinCapError::Portal(String::from(
+                    "The portal does not offer the requested input devices",
+                )));
+            }
+        }
+        self.devices = devices;
+        Ok(self)
+    }
+
+    /// Only offers sources of the `types` bitmask in the dialog, `0` offers every type the
+    /// portal supports
+    pub fn source_types(mut self, types: u32) -> Result<Self, LinCapError> {
+        if types != 0 {
+            let missing = types & !self.proxy.available_source_types()?;
+            if missing != 0 {
+                return Err(LinCapError::Portal(format!(
+                    "The portal does not offer {} sources",
+                    source_type_names(missing)
+                )));
+            }
+        }
+        self.source_types = types;
+        Ok(self)
     }
 
     pub fn show_cursor(mut self, mode: bool) -> Result<Self, LinCapError> {
@@ -458,6 +817,91 @@ impl<'a> ScreenCastPortal<'a> {
             return Ok(self);
         }
 
-        Err(LinCapError::new("Unsupported cursor mode".to_string()))
+        Err(LinCapError::Portal("Unsupported cursor mode".to_string()))
+    }
+
+    /// Requests the cursor as stream metadata instead of embedded in the frames
+    pub fn cursor_metadata(mut self) -> Result<Self, LinCapError> {
+        let available_modes = self.proxy.available_cursor_modes()?;
+        if available_modes & 4 == 4 {
+            self.cursor_mode = 4;
+            return Ok(self);
+        }
+
+        Err(LinCapError::Portal(
+            "Cursor metadata is not supported by the portal".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(value: impl RefArg + 'static) -> Variant<Box<dyn RefArg>> {
+        Variant(Box::new(value))
+    }
+
+    #[test]
+    fn reads_every_stream() {
+        let mut first = PropMap::new();
+        first.insert(String::from("position"), property((1920, 0)));
+        first.insert(String::from("size"), property((2560, 1440)));
+        first.insert(String::from("id"), property(String::from("0")));
+        first.insert(String::from("source_type"), property(1_u32));
+        first.insert(String::from("mapping_id"), property(String::from("DP-1")));
+        let mut results = PropMap::new();
+        results.insert(
+            String::from("streams"),
+            property(vec![(42_u32, first), (43_u32, PropMap::new())]),
+        );
+        // Read the results back the way they arrive in a `Response` signal
+        let results: PropMap = dbus::Message::new_signal("/", "org.example", "Response")
+            .unwrap()
+            .append1(results)
+            .read1()
+            .unwrap();
+
+        let streams = Stream::from_dbus(&results["streams"]).unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].pw_node_id(), 42);
+        assert_eq!(streams[0].position(), Some((1920, 0)));
+        assert_eq!(streams[0].size(), Some((2560, 1440)));
+        assert_eq!(streams[0].id(), Some("0"));
+        assert_eq!(streams[0].source_type(), Some(1));
+        assert_eq!(streams[0].mapping_id(), Some("DP-1"));
+        assert_eq!(streams[1].pw_node_id(), 43);
+        assert_eq!(streams[1].position(), None);
+    }
+
+    #[test]
+    fn names_source_types() {
+        assert_eq!(source_type_names(2), "window");
+        assert_eq!(source_type_names(5), "monitor or virtual");
+    }
+
+    #[test]
+    fn derives_request_path_from_sender() {
+        assert_eq!(
+            request_path(":1.42", "sc_cap_request_7").to_string(),
+            "/org/freedesktop/portal/desktop/request/1_42/sc_cap_request_7"
+        );
+    }
+
+    #[test]
+    fn distinguishes_how_requests_end() {
+        let response = |response| OrgFreedesktopPortalRequestResponse {
+            response,
+            results: PropMap::new(),
+        };
+        assert!(response_results(response(0)).is_ok());
+        assert!(matches!(
+            response_results(response(1)),
+            Err(LinCapError::Cancelled)
+        ));
+        assert!(matches!(
+            response_results(response(2)),
+            Err(LinCapError::Ended)
+        ));
     }
 }
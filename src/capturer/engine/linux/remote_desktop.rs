@@ -0,0 +1,502 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use dbus::{
+    arg::{self, PropMap},
+    blocking::{self, Connection},
+};
+
+use super::{
+    composite::{Compositor, Placement},
+    error::LinCapError,
+    portal::Stream,
+};
+use crate::capturer::{CaptureError, InputDevice, Point};
+
+// This code was autogenerated with `dbus-codegen-rust -d org.freedesktop.portal.Desktop -p /org/freedesktop/portal/desktop -f org.freedesktop.portal.RemoteDesktop`, see https://github.com/diwic/dbus-rs
+// {
+pub(super) trait OrgFreedesktopPortalRemoteDesktop {
+    fn create_session(&self, options: arg::PropMap) -> Result<dbus::Path<'static>, dbus::Error>;
+    fn select_devices(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+    ) -> Result<dbus::Path<'static>, dbus::Error>;
+    fn start(
+        &self,
+        session_handle: dbus::Path,
+        parent_window: &str,
+        options: arg::PropMap,
+    ) -> Result<dbus::Path<'static>, dbus::Error>;
+    fn notify_pointer_motion(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        dx: f64,
+        dy: f64,
+    ) -> Result<(), dbus::Error>;
+    fn notify_pointer_motion_absolute(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        stream: u32,
+        x: f64,
+        y: f64,
+    ) -> Result<(), dbus::Error>;
+    fn notify_pointer_button(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        button: i32,
+        state: u32,
+    ) -> Result<(), dbus::Error>;
+    fn notify_pointer_axis(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        dx: f64,
+        dy: f64,
+    ) -> Result<(), dbus::Error>;
+    fn notify_pointer_axis_discrete(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        axis: u32,
+        steps: i32,
+    ) -> Result<(), dbus::Error>;
+    fn notify_keyboard_keycode(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        keycode: i32,
+        state: u32,
+    ) -> Result<(), dbus::Error>;
+    fn notify_keyboard_keysym(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        keysym: i32,
+        state: u32,
+    ) -> Result<(), dbus::Error>;
+    fn available_device_types(&self) -> Result<u32, dbus::Error>;
+    fn version(&self) -> Result<u32, dbus::Error>;
+}
+
+impl<'a, T: blocking::BlockingSender, C: ::std::ops::Deref<Target = T>>
+    OrgFreedesktopPortalRemoteDesktop for blocking::Proxy<'a, C>
+{
+    fn create_session(&self, options: arg::PropMap) -> Result<dbus::Path<'static>, dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "CreateSession",
+            (options,),
+        )
+        .map(|r: (dbus::Path<'static>,)| r.0)
+    }
+
+    fn select_devices(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+    ) -> Result<dbus::Path<'static>, dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "SelectDevices",
+            (session_handle, options),
+        )
+        .map(|r: (dbus::Path<'static>,)| r.0)
+    }
+
+    fn start(
+        &self,
+        session_handle: dbus::Path,
+        parent_window: &str,
+        options: arg::PropMap,
+    ) -> Result<dbus::Path<'static>, dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "Start",
+            (session_handle, parent_window, options),
+        )
+        .map(|r: (dbus::Path<'static>,)| r.0)
+    }
+
+    fn notify_pointer_motion(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        dx: f64,
+        dy: f64,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyPointerMotion",
+            (session_handle, options, dx, dy),
+        )
+    }
+
+    fn notify_pointer_motion_absolute(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        stream: u32,
+        x: f64,
+        y: f64,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyPointerMotionAbsolute",
+            (session_handle, options, stream, x, y),
+        )
+    }
+
+    fn notify_pointer_button(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        button: i32,
+        state: u32,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyPointerButton",
+            (session_handle, options, button, state),
+        )
+    }
+
+    fn notify_pointer_axis(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        dx: f64,
+        dy: f64,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyPointerAxis",
+            (session_handle, options, dx, dy),
+        )
+    }
+
+    fn notify_pointer_axis_discrete(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        axis: u32,
+        steps: i32,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyPointerAxisDiscrete",
+            (session_handle, options, axis, steps),
+        )
+    }
+
+    fn notify_keyboard_keycode(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        keycode: i32,
+        state: u32,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyKeyboardKeycode",
+            (session_handle, options, keycode, state),
+        )
+    }
+
+    fn notify_keyboard_keysym(
+        &self,
+        session_handle: dbus::Path,
+        options: arg::PropMap,
+        keysym: i32,
+        state: u32,
+    ) -> Result<(), dbus::Error> {
+        self.method_call(
+            "org.freedesktop.portal.RemoteDesktop",
+            "NotifyKeyboardKeysym",
+            (session_handle, options, keysym, state),
+        )
+    }
+
+    fn available_device_types(&self) -> Result<u32, dbus::Error> {
+        <Self as blocking::stdintf::org_freedesktop_dbus::Properties>::get(
+            self,
+            "org.freedesktop.portal.RemoteDesktop",
+            "AvailableDeviceTypes",
+        )
+    }
+
+    fn version(&self) -> Result<u32, dbus::Error> {
+        <Self as blocking::stdintf::org_freedesktop_dbus::Properties>::get(
+            self,
+            "org.freedesktop.portal.RemoteDesktop",
+            "version",
+        )
+    }
+}
+// }
+
+/// Bit of `device` in the device type bitmask of the portal
+fn device_type(device: InputDevice) -> u32 {
+    match device {
+        InputDevice::Keyboard => 1,
+        InputDevice::Pointer => 2,
+        InputDevice::Touchscreen => 4,
+    }
+}
+
+/// Device type bitmask of the portal for a set of devices
+pub(super) fn device_types(devices: &[InputDevice]) -> u32 {
+    devices
+        .iter()
+        .fold(0, |types, &device| types | device_type(device))
+}
+
+/// Where a stream sits in the logical coordinates of the portal
+#[derive(Debug, Clone, Copy)]
+struct StreamArea {
+    node_id: u32,
+    position: Option<(i32, i32)>,
+    size: Option<(i32, i32)>,
+}
+
+/// Stream under `point` of a frame of `source` that is `frame_size` pixels large, with the
+/// point in the logical coordinates of that stream
+fn locate(
+    streams: &[StreamArea],
+    composite: bool,
+    source: usize,
+    point: &Point,
+    frame_size: [u32; 2],
+) -> Option<(u32, f64, f64)> {
+    let scale =
+        |value: f64, frame: u32, logical: usize| value * logical as f64 / f64::from(frame.max(1));
+
+    if !composite {
+        let stream = streams.get(source)?;
+        let (width, height) = stream.size.map_or(
+            (frame_size[0] as usize, frame_size[1] as usize),
+            |(w, h)| (w.max(0) as usize, h.max(0) as usize),
+        );
+        return Some((
+            stream.node_id,
+            scale(point.x, frame_size[0], width),
+            scale(point.y, frame_size[1], height),
+        ));
+    }
+
+    // The frame is the virtual desktop, find the monitor the point falls on
+    let placements: Vec<Placement> = streams
+        .iter()
+        .map(|stream| Some((stream.position?, stream.size?)))
+        .collect::<Option<_>>()?;
+    let (width, height) = Compositor::canvas_size(&placements)?;
+    let left = placements.iter().map(|((x, _), _)| *x).min()?;
+    let top = placements.iter().map(|((_, y), _)| *y).min()?;
+    let x = f64::from(left) + scale(point.x, frame_size[0], width);
+    let y = f64::from(top) + scale(point.y, frame_size[1], height);
+    streams
+        .iter()
+        .zip(&placements)
+        .find(|(_, ((sx, sy), (w, h)))| {
+            let (sx, sy) = (f64::from(*sx), f64::from(*sy));
+            x >= sx && x < sx + f64::from(*w) && y >= sy && y < sy + f64::from(*h)
+        })
+        .map(|(stream, ((sx, sy), _))| (stream.node_id, x - f64::from(*sx), y - f64::from(*sy)))
+}
+
+/// Scroll axis of [`RemoteDesktopSession::notify_pointer_axis_discrete`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Vertical,
+    Horizontal,
+}
+
+/// Drives the input devices granted through the RemoteDesktop portal, on the session the
+/// frames are captured from, see [`Options::input_devices`](crate::capturer::Options).
+///
+/// Absolute pointer positions are given in the pixels of the delivered frames and mapped onto
+/// the stream they were captured from. Buttons and keycodes are Linux evdev codes, e.g.
+/// `BTN_LEFT` (`0x110`) or `KEY_A` (`30`).
+pub struct RemoteDesktopSession {
+    connection: Arc<Mutex<Connection>>,
+    session_handle: dbus::Path<'static>,
+    devices: u32,
+    streams: Vec<StreamArea>,
+    composite: bool,
+}
+
+impl RemoteDesktopSession {
+    pub(super) fn new(
+        connection: Arc<Mutex<Connection>>,
+        session_handle: dbus::Path<'static>,
+        devices: u32,
+        streams: &[Stream],
+        composite: bool,
+    ) -> Self {
+        Self {
+            connection,
+            session_handle,
+            devices,
+            streams: streams
+                .iter()
+                .map(|stream| StreamArea {
+                    node_id: stream.pw_node_id(),
+                    position: stream.position(),
+                    size: stream.size(),
+                })
+                .collect(),
+            composite,
+        }
+    }
+
+    /// Devices the user granted, which may be fewer than requested
+    pub fn devices(&self) -> Vec<InputDevice> {
+        [
+            InputDevice::Keyboard,
+            InputDevice::Pointer,
+            InputDevice::Touchscreen,
+        ]
+        .into_iter()
+        .filter(|&device| self.devices & device_type(device) != 0)
+        .collect()
+    }
+
+    /// Moves the pointer by `dx`, `dy` logical pixels
+    pub fn notify_pointer_motion(&self, dx: f64, dy: f64) -> Result<(), CaptureError> {
+        self.call(|proxy, session| proxy.notify_pointer_motion(session, PropMap::new(), dx, dy))
+    }
+
+    /// Moves the pointer to `point` of a frame of `source` that is `frame_size` pixels large,
+    /// as reported by [`VideoFrame::source`](crate::frame::VideoFrame::source) and the frame
+    /// dimensions
+    pub fn notify_pointer_motion_absolute(
+        &self,
+        source: usize,
+        point: &Point,
+        frame_size: [u32; 2],
+    ) -> Result<(), CaptureError> {
+        let (stream, x, y) = locate(&self.streams, self.composite, source, point, frame_size)
+            .ok_or_else(|| {
+                CaptureError::Portal(format!(
+                    "No captured stream at ({}, {}) of source {source}",
+                    point.x, point.y
+                ))
+            })?;
+        self.call(|proxy, session| {
+            proxy.notify_pointer_motion_absolute(session, PropMap::new(), stream, x, y)
+        })
+    }
+
+    /// Presses or releases a pointer button
+    pub fn notify_pointer_button(&self, button: i32, pressed: bool) -> Result<(), CaptureError> {
+        self.call(|proxy, session| {
+            proxy.notify_pointer_button(session, PropMap::new(), button, u32::from(pressed))
+        })
+    }
+
+    /// Scrolls smoothly by `dx`, `dy` logical pixels
+    pub fn notify_pointer_axis(&self, dx: f64, dy: f64) -> Result<(), CaptureError> {
+        self.call(|proxy, session| proxy.notify_pointer_axis(session, PropMap::new(), dx, dy))
+    }
+
+    /// Scrolls by `steps` wheel clicks, negative steps scroll up or left
+    pub fn notify_pointer_axis_discrete(&self, axis: Axis, steps: i32) -> Result<(), CaptureError> {
+        let axis = match axis {
+            Axis::Vertical => 0,
+            Axis::Horizontal => 1,
+        };
+        self.call(|proxy, session| {
+            proxy.notify_pointer_axis_discrete(session, PropMap::new(), axis, steps)
+        })
+    }
+
+    /// Presses or releases the key with the evdev `keycode`
+    pub fn notify_keyboard_keycode(&self, keycode: i32, pressed: bool) -> Result<(), CaptureError> {
+        self.call(|proxy, session| {
+            proxy.notify_keyboard_keycode(session, PropMap::new(), keycode, u32::from(pressed))
+        })
+    }
+
+    /// Presses or releases the key producing the X11 `keysym`
+    pub fn notify_keyboard_keysym(&self, keysym: i32, pressed: bool) -> Result<(), CaptureError> {
+        self.call(|proxy, session| {
+            proxy.notify_keyboard_keysym(session, PropMap::new(), keysym, u32::from(pressed))
+        })
+    }
+
+    fn call(
+        &self,
+        notify: impl FnOnce(
+            &blocking::Proxy<'_, &Connection>,
+            dbus::Path<'static>,
+        ) -> Result<(), dbus::Error>,
+    ) -> Result<(), CaptureError> {
+        let connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let proxy = connection.with_proxy(
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            Duration::from_secs(5),
+        );
+        notify(&proxy, self.session_handle.clone()).map_err(|e| LinCapError::from(e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(node_id: u32, position: (i32, i32), size: (i32, i32)) -> StreamArea {
+        StreamArea {
+            node_id,
+            position: Some(position),
+            size: Some(size),
+        }
+    }
+
+    #[test]
+    fn maps_frame_pixels_to_stream_coordinates() {
+        // A HiDPI monitor delivers frames twice its logical size
+        let streams = [
+            area(40, (0, 0), (1280, 720)),
+            area(41, (1280, 0), (800, 600)),
+        ];
+        let point = Point {
+            x: 1000.0,
+            y: 500.0,
+        };
+        assert_eq!(
+            locate(&streams, false, 0, &point, [2560, 1440]),
+            Some((40, 500.0, 250.0))
+        );
+        assert_eq!(locate(&streams, false, 2, &point, [2560, 1440]), None);
+
+        // Composited frames cover both monitors side by side
+        let point = Point { x: 1380.0, y: 50.0 };
+        assert_eq!(
+            locate(&streams, true, 0, &point, [2080, 720]),
+            Some((41, 100.0, 50.0))
+        );
+        // Below the smaller monitor there is nothing to point at
+        let point = Point {
+            x: 1380.0,
+            y: 650.0,
+        };
+        assert_eq!(locate(&streams, true, 0, &point, [2080, 720]), None);
+    }
+
+    #[test]
+    fn combines_device_types() {
+        assert_eq!(
+            device_types(&[InputDevice::Pointer, InputDevice::Keyboard]),
+            3
+        );
+    }
+}
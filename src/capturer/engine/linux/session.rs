@@ -0,0 +1,240 @@
+use std::{
+    sync::{
+        Condvar, Mutex, PoisonError,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::Duration,
+};
+
+use super::error::LinCapError;
+use crate::capturer::NegotiatedFormat;
+
+const IDLE: u8 = 0;
+const RUNNING: u8 = 1;
+const STOPPED: u8 = 2;
+
+/// Lifecycle of a single capture session.
+///
+/// Every [`LinuxCapturer`](super::LinuxCapturer) owns one of these and shares it with its
+/// PipeWire thread, so any number of sessions can be started, stopped and fail independently.
+#[derive(Debug, Default)]
+pub(crate) struct SessionState {
+    run_state: AtomicU8,
+    error: Mutex<Option<LinCapError>>,
+    format: Mutex<Option<NegotiatedFormat>>,
+    /// Signalled when the format is negotiated or the session ends
+    changed: Condvar,
+}
+
+impl SessionState {
+    pub fn start(&self) {
+        let _ = self
+            .run_state
+            .compare_exchange(IDLE, RUNNING, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub fn stop(&self) {
+        self.run_state.store(STOPPED, Ordering::Release);
+        self.notify();
+    }
+
+    /// Whether the session was neither started nor stopped yet
+    pub fn is_idle(&self) -> bool {
+        self.run_state.load(Ordering::Acquire) == IDLE
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.run_state.load(Ordering::Acquire) == STOPPED
+    }
+
+    /// Records the error that ends the session. Only the first error is kept.
+    pub fn set_error(&self, err: LinCapError) {
+        self.error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(err);
+        self.notify();
+    }
+
+    /// Records the format PipeWire negotiated for the delivered frames
+    pub fn set_format(&self, format: NegotiatedFormat) {
+        *self.format.lock().unwrap_or_else(PoisonError::into_inner) = Some(format);
+        self.changed.notify_all();
+    }
+
+    pub fn format(&self) -> Option<NegotiatedFormat> {
+        *self.format.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Blocks until the format is negotiated, or returns why it never will be
+    pub fn wait_for_format(&self) -> Result<NegotiatedFormat, LinCapError> {
+        let mut format = self.format.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(format) = *format {
+                return Ok(format);
+            }
+            if let Some(err) = self.error() {
+                return Err(err);
+            }
+            if self.is_stopped() {
+                return Err(LinCapError::Disconnected);
+            }
+            format = self
+                .changed
+                .wait(format)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Wakes the waiters for the format. Taking the lock first makes sure a waiter either
+    /// sees the new state or is already waiting
+    fn notify(&self) {
+        drop(self.format.lock().unwrap_or_else(PoisonError::into_inner));
+        self.changed.notify_all();
+    }
+
+    pub fn error(&self) -> Option<LinCapError> {
+        self.error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn is_running(&self) -> bool {
+        self.run_state.load(Ordering::Acquire) == RUNNING
+            && self
+                .error
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_none()
+    }
+
+    /// Waits for the session to be started, then calls `iterate` until it is stopped or fails.
+    ///
+    /// Returns the error that ended the session. It is read before the caller tears down the
+    /// stream, so consumers that see the frame channel disconnect can always find out why.
+    pub fn run(&self, mut iterate: impl FnMut()) -> Result<(), LinCapError> {
+        while self.run_state.load(Ordering::Acquire) == IDLE {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        while self.is_running() {
+            iterate();
+        }
+
+        match self.error() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, mpsc},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// Runs a synthetic stream that emits an increasing frame number on every loop iteration
+    fn spawn_synthetic_stream(
+        session: Arc<SessionState>,
+    ) -> (
+        mpsc::Receiver<u64>,
+        thread::JoinHandle<Result<(), LinCapError>>,
+    ) {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut frame = 0;
+            session.run(|| {
+                let _ = tx.send(frame);
+                frame += 1;
+                thread::sleep(Duration::from_millis(1));
+            })
+        });
+        (rx, handle)
+    }
+
+    #[test]
+    fn sessions_run_independently() {
+        let first = Arc::new(SessionState::default());
+        let second = Arc::new(SessionState::default());
+        let (first_rx, first_handle) = spawn_synthetic_stream(first.clone());
+        let (second_rx, second_handle) = spawn_synthetic_stream(second.clone());
+
+        // Starting one session does not start the other
+        first.start();
+        assert!(first_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(second_rx.try_recv().is_err());
+
+        second.start();
+        assert!(second_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        // A failing session does not take the other one down
+        first.set_error(LinCapError::PipeWire(String::from("synthetic failure")));
+        assert!(matches!(
+            first_handle.join().unwrap(),
+            Err(LinCapError::PipeWire(_))
+        ));
+        while second_rx.try_recv().is_ok() {}
+        assert!(second_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(second.error().is_none());
+
+        second.stop();
+        assert!(second_handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn waits_for_negotiated_format() {
+        let session = Arc::new(SessionState::default());
+        let format = NegotiatedFormat {
+            pixel_format: crate::frame::PixelFormat::BGRx,
+            width: 1920,
+            height: 1080,
+        };
+        let negotiation = thread::spawn({
+            let session = session.clone();
+            move || {
+                thread::sleep(Duration::from_millis(10));
+                session.set_format(format);
+            }
+        });
+        assert_eq!(session.wait_for_format().unwrap(), format);
+        negotiation.join().unwrap();
+
+        // A session that fails before the negotiation reports the failure
+        let failed = SessionState::default();
+        failed.set_error(LinCapError::Format(String::from("no common format")));
+        assert!(matches!(
+            failed.wait_for_format(),
+            Err(LinCapError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn stop_before_start_ends_session() {
+        let session = Arc::new(SessionState::default());
+        let (rx, handle) = spawn_synthetic_stream(session.clone());
+
+        session.stop();
+        assert!(handle.join().unwrap().is_ok());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn closed_session_ends_without_error() {
+        let session = Arc::new(SessionState::default());
+        let (rx, handle) = spawn_synthetic_stream(session.clone());
+        session.start();
+        rx.recv().unwrap();
+
+        // What the portal closing the session from the shell does
+        session.stop();
+        assert!(handle.join().unwrap().is_ok());
+        assert!(session.is_stopped());
+        assert!(session.error().is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use std::sync::atomic::AtomicBool;
-use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
 use std::{cmp, sync::Arc};
 
 use cidre::mach;
@@ -18,7 +18,10 @@ use crate::{
     targets,
 };
 
-use super::ChannelItem;
+use super::{
+    ChannelItem, channel,
+    clock::{FrameClock, to_system_time},
+};
 
 pub(crate) mod ext;
 mod pixel_buffer;
@@ -53,7 +56,7 @@ impl sc::stream::DelegateImpl for ErrorHandler {
 
 #[repr(C)]
 pub struct CapturerInner {
-    pub tx: mpsc::Sender<ChannelItem>,
+    pub tx: channel::Sender<ChannelItem>,
 }
 
 define_obj_type!(pub Capturer + StreamOutputImpl, CapturerInner, CAPTURER);
@@ -81,13 +84,15 @@ pub(crate) enum CreateCapturerError {
     WindowNotFound(String),
     #[error("Display with title '{0}' not found")]
     DisplayNotFound(String),
+    #[error("Synthetic targets are not captured by ScreenCaptureKit")]
+    UnsupportedTarget,
 }
 
 type CapturerInit = (arc::R<Capturer>, arc::R<ErrorHandler>, arc::R<sc::Stream>);
 
 pub(crate) fn create_capturer(
     options: &Options,
-    tx: mpsc::Sender<ChannelItem>,
+    tx: channel::Sender<ChannelItem>,
     error_flag: Arc<AtomicBool>,
 ) -> Result<CapturerInit, CreateCapturerError> {
     // If no target is specified, capture the main display
@@ -147,6 +152,7 @@ pub(crate) fn create_capturer(
                 }
             }
         }
+        Target::Synthetic(_) => return Err(CreateCapturerError::UnsupportedTarget),
     };
 
     let crop_area = get_crop_area(options);
@@ -274,33 +280,28 @@ pub fn get_crop_area(options: &Options) -> Area {
         })
 }
 
+/// Capture time of `sample` and the current time, both on the monotonic mach host clock
+pub(crate) fn host_times(sample: &cm::SampleBuf) -> (Duration, Duration) {
+    let timebase = mach::TimeBaseInfo::new();
+    let to_duration = |ticks: u64| {
+        Duration::from_nanos(
+            (ticks as u128 * timebase.numer as u128 / timebase.denom as u128) as u64,
+        )
+    };
+
+    let now = mach::abs_time();
+    let capture = cm::Clock::convert_host_time_to_sys_units(sample.pts());
+    (to_duration(capture), to_duration(now))
+}
+
 pub fn process_sample_buffer(
     mut sample: arc::R<cm::SampleBuf>,
     of_type: sc::stream::OutputType,
     output_type: FrameType,
+    clock: &FrameClock,
 ) -> Option<Frame> {
-    let system_time = std::time::SystemTime::now();
-    let system_mach_time = mach::abs_time();
-
-    let frame_cm_time = sample.pts();
-    let frame_mach_time = cm::Clock::convert_host_time_to_sys_units(frame_cm_time);
-
-    let mach_time_diff = if frame_mach_time > system_mach_time {
-        (frame_mach_time - system_mach_time) as i64
-    } else {
-        -((system_mach_time - frame_mach_time) as i64)
-    };
-
-    // Convert mach time difference to nanoseconds
-    let mach_timebase = mach::TimeBaseInfo::new();
-    let nanos_diff = (mach_time_diff * mach_timebase.numer as i64) / mach_timebase.denom as i64;
-
-    // Calculate frame SystemTime
-    let frame_system_time = if nanos_diff >= 0 {
-        system_time + std::time::Duration::from_nanos(nanos_diff as u64)
-    } else {
-        system_time - std::time::Duration::from_nanos((-nanos_diff) as u64)
-    };
+    let (capture_time, now) = host_times(&sample);
+    let frame_system_time = to_system_time(capture_time, now, SystemTime::now());
 
     match of_type {
         sc::stream::OutputType::Screen => {
@@ -316,29 +317,46 @@ pub fn process_sample_buffer(
                 .unwrap()
             {
                 0 => unsafe {
+                    let (pts, sequence) = clock.video(capture_time);
                     return Some(Frame::Video(match output_type {
                         FrameType::YUVFrame => {
-                            let yuvframe =
-                                pixelformat::create_yuv_frame(sample.as_mut(), frame_system_time)
-                                    .unwrap();
+                            let yuvframe = pixelformat::create_yuv_frame(
+                                sample.as_mut(),
+                                frame_system_time,
+                                pts,
+                                sequence,
+                            )
+                            .unwrap();
                             VideoFrame::YUVFrame(yuvframe)
                         }
                         FrameType::RGB => {
-                            let rgbframe =
-                                pixelformat::create_rgb_frame(sample.as_mut(), frame_system_time)
-                                    .unwrap();
+                            let rgbframe = pixelformat::create_rgb_frame(
+                                sample.as_mut(),
+                                frame_system_time,
+                                pts,
+                                sequence,
+                            )
+                            .unwrap();
                             VideoFrame::RGB(rgbframe)
                         }
                         FrameType::BGR0 => {
-                            let bgrframe =
-                                pixelformat::create_bgr_frame(sample.as_mut(), frame_system_time)
-                                    .unwrap();
+                            let bgrframe = pixelformat::create_bgr_frame(
+                                sample.as_mut(),
+                                frame_system_time,
+                                pts,
+                                sequence,
+                            )
+                            .unwrap();
                             VideoFrame::BGR0(bgrframe)
                         }
                         FrameType::BGRAFrame => {
-                            let bgraframe =
-                                pixelformat::create_bgra_frame(sample.as_mut(), frame_system_time)
-                                    .unwrap();
+                            let bgraframe = pixelformat::create_bgra_frame(
+                                sample.as_mut(),
+                                frame_system_time,
+                                pts,
+                                sequence,
+                            )
+                            .unwrap();
                             VideoFrame::BGRA(bgraframe)
                         }
                     }));
@@ -346,11 +364,19 @@ pub fn process_sample_buffer(
                 1 => {
                     // Quick hack - just send an empty frame, and the caller can figure out how to handle it
                     if let FrameType::BGRAFrame = output_type {
+                        let (pts, sequence) = clock.video(capture_time);
                         return Some(Frame::Video(VideoFrame::BGRA(BGRAFrame {
                             display_time: frame_system_time,
+                            pts,
+                            sequence,
                             width: 0,
                             height: 0,
-                            data: vec![],
+                            data: Vec::new().into(),
+                            stride: 0,
+                            damage: None,
+                            cursor: None,
+                            repeat: false,
+                            source: 0,
                         })));
                     }
                 }
@@ -369,15 +395,19 @@ pub fn process_sample_buffer(
                 });
             }
 
-            Some(Frame::Audio(AudioFrame::new(
-                AudioFormat::F32,
-                2,
-                false,
-                bytes,
-                sample.num_samples() as usize,
-                48_000,
-                frame_system_time,
-            )))
+            let (pts, sequence) = clock.audio(capture_time);
+            Some(Frame::Audio(
+                AudioFrame::new(
+                    AudioFormat::F32,
+                    2,
+                    false,
+                    bytes,
+                    sample.num_samples() as usize,
+                    48_000,
+                    frame_system_time,
+                )
+                .with_timing(pts, sequence),
+            ))
         }
         _ => None,
     }
@@ -1,7 +1,7 @@
 use cidre::{arc, cm, sc};
-use std::sync::mpsc;
+use std::{any::Any, sync::mpsc};
 
-use crate::capturer::RawCapturer;
+use crate::capturer::{RawCapturer, engine::Engine};
 
 impl RawCapturer<'_> {
     pub fn get_next_sample_buffer(
@@ -9,18 +9,19 @@ impl RawCapturer<'_> {
     ) -> Result<(arc::R<cm::SampleBuf>, sc::stream::OutputType), mpsc::RecvError> {
         use std::time::Duration;
 
-        let capturer = &self.capturer;
+        // Only the ScreenCaptureKit engine produces sample buffers
+        let backend: &dyn Any = &*self.capturer.backend;
+        let Some(engine) = backend.downcast_ref::<Engine>() else {
+            return Err(mpsc::RecvError);
+        };
 
         loop {
-            let error_flag = capturer
-                .engine
-                .error_flag
-                .load(std::sync::atomic::Ordering::Relaxed);
+            let error_flag = engine.error_flag.load(std::sync::atomic::Ordering::Relaxed);
             if error_flag {
                 return Err(mpsc::RecvError);
             }
 
-            return match capturer.rx.recv_timeout(Duration::from_millis(10)) {
+            return match engine.rx.recv_timeout(Duration::from_millis(10)) {
                 Ok(v) => Ok(v),
                 Err(mpsc::RecvTimeoutError::Timeout) => continue,
                 Err(mpsc::RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
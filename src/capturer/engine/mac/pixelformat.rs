@@ -1,4 +1,4 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use cidre::{cm, cv};
 
@@ -10,6 +10,8 @@ use crate::frame::{
 pub unsafe fn create_yuv_frame(
     sample_buffer: &mut cm::SampleBuf,
     display_time: SystemTime,
+    pts: Duration,
+    sequence: u64,
 ) -> Option<YUVFrame> {
     let image_buffer = sample_buffer.image_buf_mut().unwrap();
 
@@ -54,18 +56,26 @@ pub unsafe fn create_yuv_frame(
 
     Some(YUVFrame {
         display_time,
+        pts,
+        sequence,
         width: width as i32,
         height: height as i32,
         luminance_bytes,
         luminance_stride: luminance_stride as i32,
         chrominance_bytes,
         chrominance_stride: chrominance_stride as i32,
+        damage: None,
+        cursor: None,
+        repeat: false,
+        source: 0,
     })
 }
 
 pub unsafe fn create_bgr_frame(
     sample_buffer: &mut cm::SampleBuf,
     display_time: SystemTime,
+    pts: Duration,
+    sequence: u64,
 ) -> Option<BGRFrame> {
     let image_buffer = sample_buffer.image_buf_mut().unwrap();
 
@@ -96,15 +106,24 @@ pub unsafe fn create_bgr_frame(
 
     Some(BGRFrame {
         display_time,
+        pts,
+        sequence,
         width: width as i32, // width does not give accurate results - https://stackoverflow.com/questions/19587185/cvpixelbuffergetbytesperrow-for-cvimagebufferref-returns-unexpected-wrong-valu
         height: height as i32,
-        data: remove_alpha_channel(cropped_data),
+        data: remove_alpha_channel(cropped_data).into(),
+        stride: width as i32 * 3,
+        damage: None,
+        cursor: None,
+        repeat: false,
+        source: 0,
     })
 }
 
 pub unsafe fn create_bgra_frame(
     sample_buffer: &mut cm::SampleBuf,
     display_time: SystemTime,
+    pts: Duration,
+    sequence: u64,
 ) -> Option<BGRAFrame> {
     let image_buffer = sample_buffer.image_buf_mut().unwrap();
 
@@ -140,15 +159,24 @@ pub unsafe fn create_bgra_frame(
 
     Some(BGRAFrame {
         display_time,
+        pts,
+        sequence,
         width: width as i32, // width does not give accurate results - https://stackoverflow.com/questions/19587185/cvpixelbuffergetbytesperrow-for-cvimagebufferref-returns-unexpected-wrong-valu
         height: height as i32,
-        data,
+        data: data.into(),
+        stride: width as i32 * 4,
+        damage: None,
+        cursor: None,
+        repeat: false,
+        source: 0,
     })
 }
 
 pub unsafe fn create_rgb_frame(
     sample_buffer: &mut cm::SampleBuf,
     display_time: SystemTime,
+    pts: Duration,
+    sequence: u64,
 ) -> Option<RGBFrame> {
     let image_buffer = sample_buffer.image_buf_mut().unwrap();
 
@@ -180,8 +208,15 @@ pub unsafe fn create_rgb_frame(
 
     Some(RGBFrame {
         display_time,
+        pts,
+        sequence,
         width: width as i32, // width does not give accurate results - https://stackoverflow.com/questions/19587185/cvpixelbuffergetbytesperrow-for-cvimagebufferref-returns-unexpected-wrong-valu
         height: height as i32,
-        data: convert_bgra_to_rgb(cropped_data),
+        data: convert_bgra_to_rgb(cropped_data).into(),
+        stride: width as i32 * 3,
+        damage: None,
+        cursor: None,
+        repeat: false,
+        source: 0,
     })
 }
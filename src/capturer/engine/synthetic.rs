@@ -0,0 +1,554 @@
+//! Test-pattern engine that runs without a display server, portal or capture permission.
+//!
+//! Frames only depend on the [`Synthetic`] source and the frame number, so a pipeline fed by
+//! this engine can be tested deterministically on a headless machine.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{channel, clock::FrameClock};
+use crate::{
+    capturer::{
+        CaptureError, NegotiatedFormat, Options, OptionsError, QueuePolicy,
+        backend::{Capabilities, CaptureBackend},
+    },
+    frame::{
+        AudioFormat, AudioFrame, BGRAFrame, BGRFrame, Frame, FrameType, PixelFormat, RGBFrame,
+        VideoFrame, YUVFrame, convert_bgra_to_rgb, remove_alpha_channel,
+    },
+    targets::{Synthetic, TestPattern},
+};
+
+const DEFAULT_FPS: u32 = 30;
+const AUDIO_SAMPLE_RATE: u32 = 48_000;
+const AUDIO_CHANNELS: u16 = 2;
+
+/// Synthetic frames have no cursor and are never cropped or scaled, only the tone can be enabled
+pub(crate) const CAPABILITIES: Capabilities = Capabilities {
+    target: true,
+    audio: true,
+    exclude_process_audio: false,
+    crop: false,
+    output_type: true,
+    scaling: false,
+    excluded_targets: false,
+    cursor: false,
+    cursor_metadata: false,
+    highlight: false,
+    multi_source: false,
+    restore_session: false,
+    source_kinds: false,
+    input_devices: false,
+};
+
+pub struct SyntheticEngine {
+    source: Synthetic,
+    fps: u32,
+    output_type: FrameType,
+    captures_audio: bool,
+    queue_policy: QueuePolicy,
+    rx: channel::Receiver<Frame>,
+    running: Arc<AtomicBool>,
+    generator: Option<JoinHandle<()>>,
+}
+
+impl SyntheticEngine {
+    /// Fails if the source has no pixels to render.
+    ///
+    /// Frames are only queued once the engine is started, until then the receiver reports
+    /// the channel as disconnected.
+    pub fn new(source: Synthetic, options: &Options) -> Result<Self, CaptureError> {
+        if source.width == 0 || source.height == 0 {
+            return Err(CaptureError::Backend(
+                OptionsError::EmptySyntheticSource.to_string(),
+            ));
+        }
+
+        // Each start opens a new channel, this one only stands in until then
+        let (_, rx) = channel::with_policy(options.queue_policy);
+        Ok(Self {
+            source,
+            fps: if options.fps == 0 {
+                DEFAULT_FPS
+            } else {
+                options.fps
+            },
+            output_type: options.output_type,
+            captures_audio: options.captures_audio,
+            queue_policy: options.queue_policy,
+            rx,
+            running: Arc::new(AtomicBool::new(false)),
+            generator: None,
+        })
+    }
+}
+
+impl CaptureBackend for SyntheticEngine {
+    fn start(&mut self) -> Result<(), CaptureError> {
+        if self.generator.is_some() {
+            return Ok(());
+        }
+
+        self.running.store(true, Ordering::Release);
+        let generator = Generator {
+            source: self.source.clone(),
+            fps: self.fps as u64,
+            output_type: self.output_type,
+            captures_audio: self.captures_audio,
+        };
+        // The generator owns the only sender, so the channel disconnects once it exits
+        let (tx, rx) = channel::with_policy(self.queue_policy);
+        self.rx = rx;
+        let running = self.running.clone();
+        self.generator = Some(std::thread::spawn(move || generator.run(&tx, &running)));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        self.running.store(false, Ordering::Release);
+        self.rx.close();
+        if let Some(handle) = self.generator.take() {
+            handle.join().map_err(|_| {
+                CaptureError::Backend(String::from("Synthetic frame generator panicked"))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn poll_next_frame(&self, cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+        self.rx.poll_recv(cx)
+    }
+
+    fn output_frame_size(&self) -> [u32; 2] {
+        [self.source.width, self.source.height]
+    }
+
+    fn wait_for_format(&self) -> Result<NegotiatedFormat, CaptureError> {
+        Ok(NegotiatedFormat {
+            pixel_format: match self.output_type {
+                FrameType::YUVFrame => PixelFormat::YUV,
+                FrameType::BGR0 => PixelFormat::BGR0,
+                FrameType::RGB => PixelFormat::RGB,
+                FrameType::BGRAFrame => PixelFormat::BGRA,
+            },
+            width: self.source.width,
+            height: self.source.height,
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
+
+    fn dropped_frames(&self) -> u64 {
+        self.rx.dropped()
+    }
+}
+
+impl Drop for SyntheticEngine {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+struct Generator {
+    source: Synthetic,
+    fps: u64,
+    output_type: FrameType,
+    captures_audio: bool,
+}
+
+impl Generator {
+    fn run(&self, tx: &channel::Sender<Frame>, running: &AtomicBool) {
+        let start = Instant::now();
+        let start_time = SystemTime::now();
+        let clock = FrameClock::default();
+        let mut audio_samples_sent = 0_u64;
+
+        for frame_number in 0_u64.. {
+            let offset = Duration::from_nanos(frame_number * 1_000_000_000 / self.fps);
+            if let Some(wait) = offset.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+            if !running.load(Ordering::Acquire) {
+                return;
+            }
+
+            let display_time = start_time + offset;
+            let video = self.render(frame_number, offset, display_time);
+            if tx.send(Frame::Video(video)).is_err() {
+                return;
+            }
+
+            if let (true, Some(frequency)) = (self.captures_audio, self.source.audio_tone) {
+                // Keep the audio in step with the video clock, one chunk per video frame
+                let due = (frame_number + 1) * AUDIO_SAMPLE_RATE as u64 / self.fps;
+                let count = due - audio_samples_sent;
+                let (pts, sequence) = clock.audio(Duration::from_nanos(
+                    audio_samples_sent * 1_000_000_000 / AUDIO_SAMPLE_RATE as u64,
+                ));
+                let audio = tone(
+                    frequency,
+                    audio_samples_sent,
+                    count as usize,
+                    start_time + pts,
+                )
+                .with_timing(pts, sequence);
+                audio_samples_sent += count;
+                if tx.send(Frame::Audio(audio)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn render(&self, frame_number: u64, offset: Duration, display_time: SystemTime) -> VideoFrame {
+        let width = self.source.width as usize;
+        let height = self.source.height as usize;
+        let bgra = render_bgra(&self.source, frame_number, offset);
+
+        match self.output_type {
+            FrameType::BGRAFrame => VideoFrame::BGRA(BGRAFrame {
+                display_time,
+                pts: offset,
+                sequence: frame_number,
+                width: width as i32,
+                height: height as i32,
+                data: bgra.into(),
+                stride: width as i32 * 4,
+                damage: None,
+                cursor: None,
+                repeat: false,
+                source: 0,
+            }),
+            FrameType::BGR0 => VideoFrame::BGR0(BGRFrame {
+                display_time,
+                pts: offset,
+                sequence: frame_number,
+                width: width as i32,
+                height: height as i32,
+                data: remove_alpha_channel(bgra).into(),
+                stride: width as i32 * 3,
+                damage: None,
+                cursor: None,
+                repeat: false,
+                source: 0,
+            }),
+            FrameType::RGB => VideoFrame::RGB(RGBFrame {
+                display_time,
+                pts: offset,
+                sequence: frame_number,
+                width: width as i32,
+                height: height as i32,
+                data: convert_bgra_to_rgb(bgra).into(),
+                stride: width as i32 * 3,
+                damage: None,
+                cursor: None,
+                repeat: false,
+                source: 0,
+            }),
+            FrameType::YUVFrame => {
+                let (luminance_bytes, chrominance_bytes) = bgra_to_nv12(&bgra, width, height);
+                VideoFrame::YUVFrame(YUVFrame {
+                    display_time,
+                    pts: offset,
+                    sequence: frame_number,
+                    width: width as i32,
+                    height: height as i32,
+                    luminance_bytes,
+                    luminance_stride: width as i32,
+                    chrominance_bytes,
+                    chrominance_stride: (width.div_ceil(2) * 2) as i32,
+                    damage: None,
+                    cursor: None,
+                    repeat: false,
+                    source: 0,
+                })
+            }
+        }
+    }
+}
+
+/// Renders the test pattern for `frame_number` as tightly packed BGRA
+pub(crate) fn render_bgra(source: &Synthetic, frame_number: u64, offset: Duration) -> Vec<u8> {
+    let width = source.width as usize;
+    let height = source.height as usize;
+    let mut canvas = vec![0_u8; width * height * 4];
+
+    match source.pattern {
+        TestPattern::ColorBars => {
+            // 75% SMPTE bars: white, yellow, cyan, green, magenta, red, blue
+            const BARS: [[u8; 3]; 7] = [
+                [191, 191, 191],
+                [0, 191, 191],
+                [191, 191, 0],
+                [0, 191, 0],
+                [191, 0, 191],
+                [0, 0, 191],
+                [191, 0, 0],
+            ];
+            for (i, pixel) in canvas.chunks_exact_mut(4).enumerate() {
+                let bar = (i % width) * BARS.len() / width;
+                let [b, g, r] = BARS[bar];
+                pixel.copy_from_slice(&[b, g, r, 255]);
+            }
+        }
+        TestPattern::MovingBox => {
+            for pixel in canvas.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[32, 32, 32, 255]);
+            }
+            let side = (width.min(height) / 8).max(1);
+            let x = bounce(frame_number * 4, width - side);
+            let y = bounce(frame_number * 3, height - side);
+            fill_rect(&mut canvas, width, x, y, side, side, [255, 255, 255]);
+        }
+    }
+
+    let scale = (height / 120).max(1);
+    let millis = offset.as_millis();
+    let timestamp = format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    );
+    draw_text(
+        &mut canvas,
+        width,
+        height,
+        scale,
+        0,
+        &frame_number.to_string(),
+    );
+    draw_text(&mut canvas, width, height, scale, 1, &timestamp);
+
+    canvas
+}
+
+/// Position that moves back and forth between `0` and `max`
+fn bounce(step: u64, max: usize) -> usize {
+    if max == 0 {
+        return 0;
+    }
+    let period = 2 * max as u64;
+    let pos = step % period;
+    (if pos > max as u64 { period - pos } else { pos }) as usize
+}
+
+fn fill_rect(
+    canvas: &mut [u8],
+    width: usize,
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    [b, g, r]: [u8; 3],
+) {
+    let height = canvas.len() / 4 / width;
+    for row in y..(y + h).min(height) {
+        for col in x..(x + w).min(width) {
+            let i = (row * width + col) * 4;
+            canvas[i..i + 4].copy_from_slice(&[b, g, r, 255]);
+        }
+    }
+}
+
+/// 3x5 glyphs, one row per byte with the most significant of the three bits on the left
+const GLYPHS: [(char, [u8; 5]); 12] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+];
+
+/// Burns `text` into the top left corner on a black background, `line` counts from the top
+fn draw_text(
+    canvas: &mut [u8],
+    width: usize,
+    height: usize,
+    scale: usize,
+    line: usize,
+    text: &str,
+) {
+    let advance = 4 * scale;
+    let line_height = 7 * scale;
+    let top = line * line_height;
+    fill_rect(
+        canvas,
+        width,
+        0,
+        top,
+        text.len() * advance + scale,
+        line_height,
+        [0, 0, 0],
+    );
+
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c) else {
+            continue;
+        };
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    let x = scale + i * advance + col * scale;
+                    let y = top + scale + row * scale;
+                    if y < height {
+                        fill_rect(canvas, width, x, y, scale, scale, [255, 255, 255]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Converts BGRA to NV12 using BT.601 limited range coefficients
+fn bgra_to_nv12(bgra: &[u8], width: usize, height: usize) -> (Vec<u8>, Vec<u8>) {
+    let chroma_width = width.div_ceil(2);
+    let chroma_height = height.div_ceil(2);
+    let mut luminance = vec![0_u8; width * height];
+    let mut chrominance = vec![0_u8; chroma_width * 2 * chroma_height];
+
+    for (i, px) in bgra.chunks_exact(4).enumerate() {
+        let (b, g, r) = (px[0] as i32, px[1] as i32, px[2] as i32);
+        luminance[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+
+        let (x, y) = (i % width, i / width);
+        if x % 2 == 0 && y % 2 == 0 {
+            let c = (y / 2) * chroma_width * 2 + (x / 2) * 2;
+            chrominance[c] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            chrominance[c + 1] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+    }
+
+    (luminance, chrominance)
+}
+
+/// Interleaved stereo sine tone starting at sample `first_sample`
+fn tone(frequency: f32, first_sample: u64, count: usize, timestamp: SystemTime) -> AudioFrame {
+    let mut data = Vec::with_capacity(count * AUDIO_CHANNELS as usize * 4);
+    for n in 0..count as u64 {
+        let t = (first_sample + n) as f64 / AUDIO_SAMPLE_RATE as f64;
+        let sample = (0.25 * (2.0 * std::f64::consts::PI * frequency as f64 * t).sin()) as f32;
+        for _ in 0..AUDIO_CHANNELS {
+            data.extend_from_slice(&sample.to_ne_bytes());
+        }
+    }
+
+    AudioFrame::new(
+        AudioFormat::F32,
+        AUDIO_CHANNELS,
+        false,
+        data,
+        count,
+        AUDIO_SAMPLE_RATE,
+        timestamp,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(pattern: TestPattern) -> Synthetic {
+        Synthetic {
+            width: 64,
+            height: 48,
+            pattern,
+            audio_tone: None,
+        }
+    }
+
+    #[test]
+    fn frames_are_deterministic() {
+        let source = source(TestPattern::MovingBox);
+        let offset = Duration::from_millis(1500);
+        assert_eq!(
+            render_bgra(&source, 45, offset),
+            render_bgra(&source, 45, offset)
+        );
+        assert_ne!(
+            render_bgra(&source, 45, offset),
+            render_bgra(&source, 46, offset)
+        );
+    }
+
+    #[test]
+    fn engine_delivers_requested_format() {
+        let options = Options {
+            fps: 120,
+            output_type: FrameType::YUVFrame,
+            captures_audio: true,
+            ..Default::default()
+        };
+        let source = Synthetic {
+            audio_tone: Some(440.0),
+            ..source(TestPattern::ColorBars)
+        };
+        let mut engine = SyntheticEngine::new(source, &options).unwrap();
+        let format = engine.wait_for_format().unwrap();
+        assert_eq!(
+            (format.pixel_format, format.width, format.height),
+            (PixelFormat::YUV, 64, 48)
+        );
+        engine.start().unwrap();
+
+        let Ok(Frame::Video(VideoFrame::YUVFrame(frame))) = engine.rx.recv() else {
+            panic!("expected a YUV frame");
+        };
+        assert_eq!((frame.width, frame.height), (64, 48));
+        assert_eq!((frame.pts, frame.sequence), (Duration::ZERO, 0));
+        assert_eq!(frame.luminance_bytes.len(), 64 * 48);
+        assert_eq!(frame.chrominance_bytes.len(), 64 * 24);
+
+        let Ok(Frame::Audio(audio)) = engine.rx.recv() else {
+            panic!("expected an audio frame");
+        };
+        assert_eq!(audio.sample_count(), AUDIO_SAMPLE_RATE as usize / 120);
+
+        let Ok(Frame::Video(frame)) = engine.rx.recv() else {
+            panic!("expected a video frame");
+        };
+        assert_eq!(frame.pts(), Duration::from_nanos(1_000_000_000 / 120));
+        assert_eq!(frame.sequence(), 1);
+
+        engine.stop().unwrap();
+    }
+
+    #[test]
+    fn channel_disconnects_after_stop() {
+        let mut engine =
+            SyntheticEngine::new(source(TestPattern::ColorBars), &Options::default()).unwrap();
+        engine.start().unwrap();
+        assert!(engine.rx.recv().is_ok());
+        engine.stop().unwrap();
+        while engine.rx.recv().is_ok() {}
+    }
+
+    #[test]
+    fn rejects_empty_source() {
+        let source = Synthetic {
+            height: 0,
+            ..source(TestPattern::MovingBox)
+        };
+        assert!(matches!(
+            SyntheticEngine::new(source, &Options::default()),
+            Err(CaptureError::Backend(_))
+        ));
+    }
+}
@@ -1,6 +1,12 @@
+use super::{
+    channel,
+    clock::{FrameClock, to_system_time},
+};
 use crate::{
     capturer::{Area, Options, Point, Resolution, Size},
-    frame::{AudioFormat, AudioFrame, BGRAFrame, Frame, FrameType, VideoFrame},
+    frame::{
+        AudioFormat, AudioFrame, BGRAFrame, Frame, FramePool, FrameType, PoolStats, VideoFrame,
+    },
     targets::{self, Target, get_scale_factor},
 };
 use ::windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
@@ -13,7 +19,10 @@ use std::{cmp, time::Duration};
 use std::{
     os::windows,
     ptr::null_mut,
-    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
 };
 use windows_capture::{
     capture::{CaptureControl, Context, GraphicsCaptureApiHandler},
@@ -29,9 +38,10 @@ use windows_capture::{
 
 #[derive(Debug)]
 struct Capturer {
-    pub tx: mpsc::Sender<Frame>,
+    pub tx: channel::Sender<Frame>,
     pub crop: Option<Area>,
-    pub start_time: (i64, SystemTime),
+    pub pool: FramePool,
+    pub clock: Arc<FrameClock>,
     pub perf_freq: i64,
 }
 
@@ -45,6 +55,7 @@ pub struct WCStream {
     settings: Settings,
     capture_control: Option<CaptureControl<Capturer, Box<dyn std::error::Error + Send + Sync>>>,
     audio_stream: Option<AudioStreamHandle>,
+    pool: FramePool,
 }
 
 impl GraphicsCaptureApiHandler for Capturer {
@@ -55,19 +66,9 @@ impl GraphicsCaptureApiHandler for Capturer {
         Ok(Self {
             tx: context.flags.tx,
             crop: context.flags.crop,
-            start_time: (
-                unsafe {
-                    let mut time = 0;
-                    QueryPerformanceCounter(&mut time);
-                    time
-                },
-                SystemTime::now(),
-            ),
-            perf_freq: unsafe {
-                let mut freq = 0;
-                QueryPerformanceFrequency(&mut freq);
-                freq
-            },
+            pool: context.flags.pool,
+            clock: context.flags.clock,
+            perf_freq: perf_frequency(),
         })
     }
 
@@ -76,14 +77,10 @@ impl GraphicsCaptureApiHandler for Capturer {
         frame: &mut WCFrame,
         _: InternalCaptureControl,
     ) -> Result<(), Self::Error> {
-        let elapsed = frame.timespan().Duration - self.start_time.0;
-        let display_time = self
-            .start_time
-            .1
-            .checked_add(Duration::from_secs_f64(
-                elapsed as f64 / self.perf_freq as f64,
-            ))
-            .unwrap();
+        let capture_time = perf_duration(frame.timespan().Duration, self.perf_freq);
+        let now = perf_duration(perf_counter(), self.perf_freq);
+        let display_time = to_system_time(capture_time, now, SystemTime::now());
+        let (pts, sequence) = self.clock.video(capture_time);
 
         match &self.crop {
             Some(cropped_area) => {
@@ -111,9 +108,16 @@ impl GraphicsCaptureApiHandler for Capturer {
 
                 let bgr_frame = BGRAFrame {
                     display_time,
+                    pts,
+                    sequence,
                     width: cropped_area.size.width as i32,
                     height: cropped_area.size.height as i32,
-                    data: raw_frame_buffer.to_vec(),
+                    data: self.pool.copy_from_slice(raw_frame_buffer),
+                    stride: cropped_area.size.width as i32 * 4,
+                    damage: None,
+                    cursor: None,
+                    repeat: false,
+                    source: 0,
                 };
 
                 let _ = self.tx.send(Frame::Video(VideoFrame::BGRA(bgr_frame)));
@@ -122,16 +126,23 @@ impl GraphicsCaptureApiHandler for Capturer {
                 // get raw frame buffer
                 let mut frame_buffer = frame.buffer().unwrap();
                 let raw_frame_buffer = frame_buffer.as_raw_buffer();
-                let frame_data = raw_frame_buffer.to_vec();
+                let frame_data = self.pool.copy_from_slice(raw_frame_buffer);
                 let current_time = SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .expect("Failed to get current time")
                     .as_nanos() as u64;
                 let bgr_frame = BGRAFrame {
                     display_time,
+                    pts,
+                    sequence,
                     width: frame.width() as i32,
                     height: frame.height() as i32,
                     data: frame_data,
+                    stride: frame.width() as i32 * 4,
+                    damage: None,
+                    cursor: None,
+                    repeat: false,
+                    source: 0,
                 };
 
                 let _ = self.tx.send(Frame::Video(VideoFrame::BGRA(bgr_frame)));
@@ -147,6 +158,10 @@ impl GraphicsCaptureApiHandler for Capturer {
 }
 
 impl WCStream {
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
     pub fn start_capture(&mut self) {
         let cc = match &self.settings {
             Settings::Display(st) => Capturer::start_free_threaded(st.to_owned()).unwrap(),
@@ -172,19 +187,43 @@ impl WCStream {
 
 #[derive(Clone, Debug)]
 struct FlagStruct {
-    pub tx: mpsc::Sender<Frame>,
+    pub tx: channel::Sender<Frame>,
     pub crop: Option<Area>,
+    pub pool: FramePool,
+    pub clock: Arc<FrameClock>,
+}
+
+fn perf_counter() -> i64 {
+    let mut time = 0;
+    unsafe {
+        let _ = QueryPerformanceCounter(&mut time);
+    }
+    time
+}
+
+fn perf_frequency() -> i64 {
+    let mut freq = 0;
+    unsafe {
+        let _ = QueryPerformanceFrequency(&mut freq);
+    }
+    freq
+}
+
+/// Converts a performance counter reading into time since boot
+fn perf_duration(ticks: i64, freq: i64) -> Duration {
+    Duration::from_secs_f64(ticks.max(0) as f64 / freq.max(1) as f64)
 }
 
 #[derive(Debug)]
 pub enum CreateCapturerError {
     AudioStreamConfig(cpal::DefaultStreamConfigError),
     BuildAudioStream(cpal::BuildStreamError),
+    UnsupportedTarget,
 }
 
 pub fn create_capturer(
     options: &Options,
-    tx: mpsc::Sender<Frame>,
+    tx: channel::Sender<Frame>,
 ) -> Result<WCStream, CreateCapturerError> {
     let target = options
         .target
@@ -210,6 +249,10 @@ pub fn create_capturer(
         DrawBorderSettings::Default
     };
 
+    let pool = FramePool::new(options.frame_pool_size);
+    // Shared by the video and audio threads so both are timed from the same origin
+    let clock = Arc::new(FrameClock::default());
+
     let settings = match target {
         Target::Display(display) => Settings::Display(WCSettings::new(
             WCMonitor::from_raw_hmonitor(display.raw_handle.0),
@@ -222,6 +265,8 @@ pub fn create_capturer(
             FlagStruct {
                 tx: tx.clone(),
                 crop: Some(get_crop_area(options)),
+                pool: pool.clone(),
+                clock: clock.clone(),
             },
         )),
         Target::Window(window) => Settings::Window(WCSettings::new(
@@ -235,15 +280,18 @@ pub fn create_capturer(
             FlagStruct {
                 tx: tx.clone(),
                 crop: Some(get_crop_area(options)),
+                pool: pool.clone(),
+                clock: clock.clone(),
             },
         )),
+        Target::Synthetic(_) => return Err(CreateCapturerError::UnsupportedTarget),
     };
 
     let audio_stream = if options.captures_audio {
         let (ctrl_tx, ctrl_rx) = mpsc::channel();
         let (ready_tx, ready_rx) = mpsc::channel();
 
-        spawn_audio_stream(tx.clone(), ready_tx, ctrl_rx);
+        spawn_audio_stream(tx.clone(), ready_tx, ctrl_rx, clock);
 
         match ready_rx.recv() {
             Ok(Ok(())) => {}
@@ -260,6 +308,7 @@ pub fn create_capturer(
         settings,
         capture_control: None,
         audio_stream,
+        pool,
     })
 }
 
@@ -343,7 +392,7 @@ enum AudioStreamControl {
 
 fn build_audio_stream(
     sample_tx: mpsc::Sender<
-        Result<(Vec<u8>, cpal::InputCallbackInfo, SystemTime), cpal::StreamError>,
+        Result<(Vec<u8>, cpal::InputCallbackInfo, SystemTime, i64), cpal::StreamError>,
     >,
 ) -> Result<(cpal::Stream, cpal::SupportedStreamConfig), CreateCapturerError> {
     let host = cpal::default_host();
@@ -365,7 +414,12 @@ fn build_audio_stream(
                 let sample_tx = sample_tx.clone();
                 move |data, info: &cpal::InputCallbackInfo| {
                     sample_tx
-                        .send(Ok((data.bytes().to_vec(), info.clone(), SystemTime::now())))
+                        .send(Ok((
+                            data.bytes().to_vec(),
+                            info.clone(),
+                            SystemTime::now(),
+                            perf_counter(),
+                        )))
                         .unwrap();
                 }
             },
@@ -380,9 +434,10 @@ fn build_audio_stream(
 }
 
 fn spawn_audio_stream(
-    tx: Sender<Frame>,
+    tx: channel::Sender<Frame>,
     ready_tx: Sender<Result<(), CreateCapturerError>>,
     ctrl_rx: Receiver<AudioStreamControl>,
+    clock: Arc<FrameClock>,
 ) {
     std::thread::spawn(move || {
         let (sample_tx, sample_rx) = mpsc::channel();
@@ -414,6 +469,7 @@ fn spawn_audio_stream(
         }
 
         let audio_format = AudioFormat::from(config.sample_format());
+        let perf_freq = perf_frequency();
 
         loop {
             match ctrl_rx.try_recv() {
@@ -422,16 +478,17 @@ fn spawn_audio_stream(
                 Err(_) => return,
             };
 
-            let (data, info, timestamp) = match sample_rx.recv_timeout(Duration::from_millis(100)) {
-                Ok(Ok(data)) => data,
-                Err(RecvTimeoutError::Timeout) => {
-                    continue;
-                }
-                _ => {
-                    // let _ = tx.send(Err(e));
-                    return;
-                }
-            };
+            let (data, info, timestamp, counter) =
+                match sample_rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok(Ok(data)) => data,
+                    Err(RecvTimeoutError::Timeout) => {
+                        continue;
+                    }
+                    _ => {
+                        // let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
 
             let sample_count =
                 data.len() / (audio_format.sample_size() * config.channels() as usize);
@@ -444,6 +501,8 @@ fn spawn_audio_stream(
                 config.sample_rate().0,
                 timestamp,
             );
+            let (pts, sequence) = clock.audio(perf_duration(counter, perf_freq));
+            let frame = frame.with_timing(pts, sequence);
 
             if let Err(_) = tx.send(Frame::Audio(frame)) {
                 return;
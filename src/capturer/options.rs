@@ -0,0 +1,465 @@
+use std::{fmt, time::Duration};
+
+use super::{
+    Area, Capabilities, InputDevice, MultiSource, Options, ParentWindow, PersistMode, PortalCancel,
+    QueuePolicy, Resolution, SourceKind, engine,
+};
+use crate::{
+    frame::FrameType,
+    targets::{self, Target},
+};
+
+/// An option that the selected backend would ignore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsupportedOption {
+    Target,
+    CapturesAudio,
+    ExcludeCurrentProcessAudio,
+    CropArea,
+    OutputType,
+    OutputResolution,
+    ExcludedTargets,
+    ShowCursor,
+    CursorMetadata,
+    ShowHighlight,
+    MultiSource,
+    RestoreToken,
+    PersistMode,
+    SourceKinds,
+    InputDevices,
+}
+
+impl fmt::Display for UnsupportedOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnsupportedOption::Target => "target",
+            UnsupportedOption::CapturesAudio => "captures_audio",
+            UnsupportedOption::ExcludeCurrentProcessAudio => "exclude_current_process_audio",
+            UnsupportedOption::CropArea => "crop_area",
+            UnsupportedOption::OutputType => "output_type",
+            UnsupportedOption::OutputResolution => "output_resolution",
+            UnsupportedOption::ExcludedTargets => "excluded_targets",
+            UnsupportedOption::ShowCursor => "show_cursor",
+            UnsupportedOption::CursorMetadata => "cursor_metadata",
+            UnsupportedOption::ShowHighlight => "show_highlight",
+            UnsupportedOption::MultiSource => "multi_source",
+            UnsupportedOption::RestoreToken => "restore_token",
+            UnsupportedOption::PersistMode => "persist_mode",
+            UnsupportedOption::SourceKinds => "source_kinds",
+            UnsupportedOption::InputDevices => "input_devices",
+        })
+    }
+}
+
+/// Reasons [`Options`] are rejected by [`OptionsBuilder::build`] and [`Options::validate`]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum OptionsError {
+    #[error("fps must be greater than 0")]
+    ZeroFps,
+    #[error("crop area must have a positive width and height")]
+    EmptyCropArea,
+    #[error("synthetic source must have a positive width and height")]
+    EmptySyntheticSource,
+    #[error("crop area {x}x{y}+{width}x{height} exceeds the {target_width}x{target_height} target")]
+    CropOutOfBounds {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        target_width: u64,
+        target_height: u64,
+    },
+    #[error("options not supported by this backend: {}", list(.0))]
+    Unsupported(Vec<UnsupportedOption>),
+}
+
+fn list(options: &[UnsupportedOption]) -> String {
+    options
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Options {
+    /// Returns a builder that validates the options against the current platform
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    /// Checks the options for values that would be ignored or fail later on a backend with
+    /// the given `capabilities`.
+    ///
+    /// Options the backend does not support are all reported together, before any value is
+    /// checked against the target.
+    pub fn validate(&self, capabilities: &Capabilities) -> Result<(), OptionsError> {
+        let unsupported = [
+            (
+                self.target
+                    .as_ref()
+                    .is_some_and(|target| !targets::is_user_selection(target))
+                    && !capabilities.target,
+                UnsupportedOption::Target,
+            ),
+            (
+                self.captures_audio && !capabilities.audio,
+                UnsupportedOption::CapturesAudio,
+            ),
+            (
+                self.exclude_current_process_audio && !capabilities.exclude_process_audio,
+                UnsupportedOption::ExcludeCurrentProcessAudio,
+            ),
+            (
+                self.crop_area.is_some() && !capabilities.crop,
+                UnsupportedOption::CropArea,
+            ),
+            (
+                !matches!(self.output_type, FrameType::YUVFrame) && !capabilities.output_type,
+                UnsupportedOption::OutputType,
+            ),
+            (
+                !matches!(self.output_resolution, Resolution::Captured) && !capabilities.scaling,
+                UnsupportedOption::OutputResolution,
+            ),
+            (
+                self.excluded_targets
+                    .as_ref()
+                    .is_some_and(|t| !t.is_empty())
+                    && !capabilities.excluded_targets,
+                UnsupportedOption::ExcludedTargets,
+            ),
+            (
+                self.show_cursor && !capabilities.cursor,
+                UnsupportedOption::ShowCursor,
+            ),
+            (
+                self.cursor_metadata && !capabilities.cursor_metadata,
+                UnsupportedOption::CursorMetadata,
+            ),
+            (
+                self.show_highlight && !capabilities.highlight,
+                UnsupportedOption::ShowHighlight,
+            ),
+            (
+                self.multi_source != MultiSource::Single && !capabilities.multi_source,
+                UnsupportedOption::MultiSource,
+            ),
+            (
+                self.restore_token.is_some() && !capabilities.restore_session,
+                UnsupportedOption::RestoreToken,
+            ),
+            (
+                self.persist_mode != PersistMode::DoNot && !capabilities.restore_session,
+                UnsupportedOption::PersistMode,
+            ),
+            (
+                !self.source_kinds.is_empty() && !capabilities.source_kinds,
+                UnsupportedOption::SourceKinds,
+            ),
+            (
+                !self.input_devices.is_empty() && !capabilities.input_devices,
+                UnsupportedOption::InputDevices,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(unsupported, option)| unsupported.then_some(option))
+        .collect::<Vec<_>>();
+
+        if !unsupported.is_empty() {
+            return Err(OptionsError::Unsupported(unsupported));
+        }
+
+        if self.fps == 0 {
+            return Err(OptionsError::ZeroFps);
+        }
+
+        if let Some(Target::Synthetic(source)) = &self.target
+            && (source.width == 0 || source.height == 0)
+        {
+            return Err(OptionsError::EmptySyntheticSource);
+        }
+
+        if let Some(crop) = &self.crop_area {
+            self.validate_crop_area(crop)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_crop_area(&self, crop: &Area) -> Result<(), OptionsError> {
+        if !(crop.size.width > 0.0 && crop.size.height > 0.0) {
+            return Err(OptionsError::EmptyCropArea);
+        }
+
+        let target = self
+            .target
+            .clone()
+            .unwrap_or_else(|| Target::Display(targets::get_main_display()));
+        let (target_width, target_height) = targets::get_target_dimensions(&target);
+
+        if crop.origin.x < 0.0
+            || crop.origin.y < 0.0
+            || crop.origin.x + crop.size.width > target_width as f64
+            || crop.origin.y + crop.size.height > target_height as f64
+        {
+            return Err(OptionsError::CropOutOfBounds {
+                x: crop.origin.x,
+                y: crop.origin.y,
+                width: crop.size.width,
+                height: crop.size.height,
+                target_width,
+                target_height,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds [`Options`], rejecting values the capture backend for the target would ignore
+#[derive(Debug, Clone)]
+pub struct OptionsBuilder {
+    options: Options,
+}
+
+impl Default for OptionsBuilder {
+    fn default() -> Self {
+        Self {
+            options: Options {
+                fps: 60,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl OptionsBuilder {
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.options.fps = fps;
+        self
+    }
+
+    pub fn constant_frame_rate(mut self, constant_frame_rate: bool) -> Self {
+        self.options.constant_frame_rate = constant_frame_rate;
+        self
+    }
+
+    pub fn show_cursor(mut self, show_cursor: bool) -> Self {
+        self.options.show_cursor = show_cursor;
+        self
+    }
+
+    pub fn cursor_metadata(mut self, cursor_metadata: bool) -> Self {
+        self.options.cursor_metadata = cursor_metadata;
+        self
+    }
+
+    pub fn show_highlight(mut self, show_highlight: bool) -> Self {
+        self.options.show_highlight = show_highlight;
+        self
+    }
+
+    pub fn target(mut self, target: Target) -> Self {
+        self.options.target = Some(target);
+        self
+    }
+
+    pub fn crop_area(mut self, crop_area: Area) -> Self {
+        self.options.crop_area = Some(crop_area);
+        self
+    }
+
+    pub fn output_type(mut self, output_type: FrameType) -> Self {
+        self.options.output_type = output_type;
+        self
+    }
+
+    pub fn output_resolution(mut self, output_resolution: Resolution) -> Self {
+        self.options.output_resolution = output_resolution;
+        self
+    }
+
+    pub fn excluded_targets(mut self, excluded_targets: Vec<Target>) -> Self {
+        self.options.excluded_targets = Some(excluded_targets);
+        self
+    }
+
+    pub fn captures_audio(mut self, captures_audio: bool) -> Self {
+        self.options.captures_audio = captures_audio;
+        self
+    }
+
+    pub fn exclude_current_process_audio(mut self, exclude: bool) -> Self {
+        self.options.exclude_current_process_audio = exclude;
+        self
+    }
+
+    pub fn queue_policy(mut self, queue_policy: QueuePolicy) -> Self {
+        self.options.queue_policy = queue_policy;
+        self
+    }
+
+    pub fn frame_pool_size(mut self, frame_pool_size: usize) -> Self {
+        self.options.frame_pool_size = frame_pool_size;
+        self
+    }
+
+    pub fn multi_source(mut self, multi_source: MultiSource) -> Self {
+        self.options.multi_source = multi_source;
+        self
+    }
+
+    pub fn restore_token(mut self, restore_token: impl Into<String>) -> Self {
+        self.options.restore_token = Some(restore_token.into());
+        self
+    }
+
+    pub fn persist_mode(mut self, persist_mode: PersistMode) -> Self {
+        self.options.persist_mode = persist_mode;
+        self
+    }
+
+    pub fn source_kinds(mut self, source_kinds: Vec<SourceKind>) -> Self {
+        self.options.source_kinds = source_kinds;
+        self
+    }
+
+    pub fn input_devices(mut self, input_devices: Vec<InputDevice>) -> Self {
+        self.options.input_devices = input_devices;
+        self
+    }
+
+    pub fn parent_window(mut self, parent_window: ParentWindow) -> Self {
+        self.options.parent_window = Some(parent_window);
+        self
+    }
+
+    pub fn portal_timeout(mut self, timeout: Duration) -> Self {
+        self.options.portal_timeout = Some(timeout);
+        self
+    }
+
+    pub fn portal_cancel(mut self, cancel: PortalCancel) -> Self {
+        self.options.portal_cancel = cancel;
+        self
+    }
+
+    /// Validates the options against the backend [`Capturer::build`](super::Capturer::build)
+    /// picks for the target on this platform
+    pub fn build(self) -> Result<Options, OptionsError> {
+        let capabilities = engine::capabilities(&self.options);
+        self.build_for(&capabilities)
+    }
+
+    /// Validates the options against a custom backend's capabilities
+    pub fn build_for(self, capabilities: &Capabilities) -> Result<Options, OptionsError> {
+        self.options.validate(capabilities)?;
+        Ok(self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        capturer::{Point, Size},
+        targets::Synthetic,
+    };
+
+    fn synthetic() -> OptionsBuilder {
+        Options::builder().target(Target::Synthetic(Synthetic {
+            width: 640,
+            height: 480,
+            ..Default::default()
+        }))
+    }
+
+    fn area(x: f64, y: f64, width: f64, height: f64) -> Area {
+        Area {
+            origin: Point { x, y },
+            size: Size { width, height },
+        }
+    }
+
+    #[test]
+    fn lists_every_unsupported_option() {
+        let err = synthetic()
+            .exclude_current_process_audio(true)
+            .output_resolution(Resolution::_720p)
+            .show_cursor(true)
+            .cursor_metadata(true)
+            .show_highlight(true)
+            .multi_source(MultiSource::Composite)
+            .persist_mode(PersistMode::Persistent)
+            .source_kinds(vec![SourceKind::Window])
+            .input_devices(vec![InputDevice::Pointer])
+            .crop_area(area(0.0, 0.0, 10.0, 10.0))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            OptionsError::Unsupported(vec![
+                UnsupportedOption::ExcludeCurrentProcessAudio,
+                UnsupportedOption::CropArea,
+                UnsupportedOption::OutputResolution,
+                UnsupportedOption::ShowCursor,
+                UnsupportedOption::CursorMetadata,
+                UnsupportedOption::ShowHighlight,
+                UnsupportedOption::MultiSource,
+                UnsupportedOption::PersistMode,
+                UnsupportedOption::SourceKinds,
+                UnsupportedOption::InputDevices,
+            ])
+        );
+        assert_eq!(
+            err.to_string(),
+            "options not supported by this backend: exclude_current_process_audio, crop_area, \
+             output_resolution, show_cursor, cursor_metadata, show_highlight, multi_source, \
+             persist_mode, source_kinds, input_devices"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(matches!(
+            synthetic().fps(0).build(),
+            Err(OptionsError::ZeroFps)
+        ));
+        assert!(matches!(
+            Options::builder()
+                .target(Target::Synthetic(Synthetic {
+                    width: 0,
+                    ..Default::default()
+                }))
+                .build(),
+            Err(OptionsError::EmptySyntheticSource)
+        ));
+
+        let cropping = Capabilities {
+            target: true,
+            crop: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            synthetic()
+                .crop_area(area(0.0, 0.0, 0.0, 10.0))
+                .build_for(&cropping),
+            Err(OptionsError::EmptyCropArea)
+        ));
+        assert!(matches!(
+            synthetic()
+                .crop_area(area(600.0, 0.0, 100.0, 100.0))
+                .build_for(&cropping),
+            Err(OptionsError::CropOutOfBounds {
+                target_width: 640,
+                ..
+            })
+        ));
+        assert!(
+            synthetic()
+                .crop_area(area(540.0, 380.0, 100.0, 100.0))
+                .build_for(&cropping)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Health of a running capture.
+//!
+//! Backends count the frames they lose before the queue through [`SourceDrops`], the queue
+//! counts the frames its [`QueuePolicy`](super::QueuePolicy) throws away, and the
+//! [`Capturer`](super::Capturer) measures rate and latency of the video frames it hands out.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Window the frame rate is measured over
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Latency samples the percentiles are computed from
+const LATENCY_SAMPLES: usize = 256;
+
+/// Frames a backend lost before they reached the queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceDrops {
+    /// The source had no buffer to put the frame in, PipeWire's "out of buffers"
+    pub out_of_buffers: u64,
+    /// The source flagged the frame as corrupted or its data could not be read
+    pub corrupted: u64,
+}
+
+/// Frames lost between the source and the consumer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DroppedFrames {
+    /// See [`SourceDrops::out_of_buffers`]
+    pub out_of_buffers: u64,
+    /// See [`SourceDrops::corrupted`]
+    pub corrupted: u64,
+    /// Discarded by [`Options::queue_policy`](super::Options::queue_policy) because the
+    /// consumer did not keep up
+    pub queue: u64,
+    /// Discarded by [`Options::constant_frame_rate`](super::Options::constant_frame_rate)
+    /// because the source was faster than `fps`
+    pub frame_rate: u64,
+}
+
+impl DroppedFrames {
+    pub fn total(&self) -> u64 {
+        self.out_of_buffers + self.corrupted + self.queue + self.frame_rate
+    }
+}
+
+/// Time from the capture of a video frame until it was handed to the consumer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Snapshot of the health of a capture, see [`Capturer::stats`](super::Capturer::stats)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CaptureStats {
+    /// Video frames handed to the consumer
+    pub frames_delivered: u64,
+    pub dropped: DroppedFrames,
+    /// Video frames delivered per second over the last second
+    pub fps: f64,
+    /// Over the last 256 video frames, `None` until a frame was delivered
+    pub latency: Option<LatencyPercentiles>,
+}
+
+#[derive(Debug, Default)]
+struct Deliveries {
+    count: u64,
+    times: VecDeque<Instant>,
+    latencies: VecDeque<Duration>,
+}
+
+/// Measures the video frames a [`Capturer`](super::Capturer) delivers
+#[derive(Debug, Default)]
+pub(crate) struct DeliveryStats {
+    deliveries: Mutex<Deliveries>,
+}
+
+impl DeliveryStats {
+    /// Records a video frame delivered at `at`, `latency` after it was captured
+    pub(crate) fn record(&self, at: Instant, latency: Duration) {
+        let mut deliveries = self
+            .deliveries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        deliveries.count += 1;
+
+        deliveries.times.push_back(at);
+        while deliveries
+            .times
+            .front()
+            .is_some_and(|&time| at.duration_since(time) >= FPS_WINDOW)
+        {
+            deliveries.times.pop_front();
+        }
+
+        if deliveries.latencies.len() == LATENCY_SAMPLES {
+            deliveries.latencies.pop_front();
+        }
+        deliveries.latencies.push_back(latency);
+    }
+
+    /// Combines the deliveries up to `now` with the frames dropped on the way
+    pub(crate) fn snapshot(&self, now: Instant, dropped: DroppedFrames) -> CaptureStats {
+        let deliveries = self
+            .deliveries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let recent = deliveries
+            .times
+            .iter()
+            .filter(|&&time| now.saturating_duration_since(time) < FPS_WINDOW)
+            .count();
+
+        let mut latencies: Vec<Duration> = deliveries.latencies.iter().copied().collect();
+        latencies.sort_unstable();
+        let percentile = |p: usize| latencies[(latencies.len() * p).div_ceil(100).max(1) - 1];
+
+        CaptureStats {
+            frames_delivered: deliveries.count,
+            dropped,
+            fps: recent as f64 / FPS_WINDOW.as_secs_f64(),
+            latency: (!latencies.is_empty()).then(|| LatencyPercentiles {
+                p50: percentile(50),
+                p90: percentile(90),
+                p99: percentile(99),
+                max: percentile(100),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_fps_over_the_last_second() {
+        let stats = DeliveryStats::default();
+        let start = Instant::now();
+        for frame in 0..90 {
+            stats.record(start + Duration::from_millis(frame * 20), Duration::ZERO);
+        }
+
+        // 50 frames fall into the second ending at the last delivery
+        let last = start + Duration::from_millis(89 * 20);
+        let snapshot = stats.snapshot(last, DroppedFrames::default());
+        assert_eq!(snapshot.frames_delivered, 90);
+        assert_eq!(snapshot.fps, 50.0);
+
+        // Without new frames the rate falls to zero
+        let snapshot = stats.snapshot(last + Duration::from_secs(2), DroppedFrames::default());
+        assert_eq!(snapshot.fps, 0.0);
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let stats = DeliveryStats::default();
+        let now = Instant::now();
+        let dropped = DroppedFrames::default();
+        assert_eq!(stats.snapshot(now, dropped).latency, None);
+
+        for ms in (1..=100).rev() {
+            stats.record(now, Duration::from_millis(ms));
+        }
+        let latency = stats.snapshot(now, dropped).latency.unwrap();
+        assert_eq!(latency.p50, Duration::from_millis(50));
+        assert_eq!(latency.p90, Duration::from_millis(90));
+        assert_eq!(latency.p99, Duration::from_millis(99));
+        assert_eq!(latency.max, Duration::from_millis(100));
+    }
+}
@@ -1,7 +1,9 @@
 mod audio;
+mod pool;
 mod video;
 
 pub use audio::*;
+pub use pool::*;
 pub use video::*;
 
 pub enum Frame {
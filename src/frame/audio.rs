@@ -1,4 +1,4 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 pub struct AudioFrame {
     format: AudioFormat,
@@ -8,6 +8,8 @@ pub struct AudioFrame {
     sample_count: usize,
     rate: u32,
     timestamp: SystemTime,
+    pts: Duration,
+    sequence: u64,
 }
 
 impl AudioFrame {
@@ -30,9 +32,17 @@ impl AudioFrame {
             sample_count,
             rate,
             timestamp,
+            pts: Duration::ZERO,
+            sequence: 0,
         }
     }
 
+    pub(crate) fn with_timing(mut self, pts: Duration, sequence: u64) -> Self {
+        self.pts = pts;
+        self.sequence = sequence;
+        self
+    }
+
     pub fn format(&self) -> AudioFormat {
         self.format
     }
@@ -61,10 +71,22 @@ impl AudioFrame {
         self.sample_count
     }
 
+    /// Wall clock time the first sample was captured at
     pub fn time(&self) -> SystemTime {
         self.timestamp
     }
 
+    /// Presentation timestamp of the first sample, on the same clock as the video frames of
+    /// the capture
+    pub fn pts(&self) -> Duration {
+        self.pts
+    }
+
+    /// Number of the frame within the audio of the capture
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
     pub fn plane_data(&self, plane: usize) -> &[u8] {
         if !self.is_planar {
             &self.data
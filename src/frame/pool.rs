@@ -0,0 +1,239 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Allocation statistics of a [`FramePool`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Buffers that had to be freshly allocated or grown
+    pub allocations: u64,
+    /// Buffers handed out again after a consumer dropped them
+    pub reuses: u64,
+    /// Buffers currently waiting in the pool
+    pub idle: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    free: Mutex<Vec<Vec<u8>>>,
+    size: usize,
+    allocations: AtomicU64,
+    reuses: AtomicU64,
+}
+
+impl Shared {
+    fn recycle(&self, mut data: Vec<u8>) {
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        if free.len() < self.size {
+            data.clear();
+            free.push(data);
+        }
+    }
+}
+
+/// Keeps the data buffers of dropped frames around so engines can fill new frames without
+/// allocating.
+///
+/// Cloning a pool gives another handle to the same buffers.
+#[derive(Clone, Default)]
+pub struct FramePool {
+    shared: Arc<Shared>,
+}
+
+impl FramePool {
+    /// Creates a pool that keeps up to `size` idle buffers, `0` disables recycling
+    pub fn new(size: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                size,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Returns an empty buffer that can hold at least `capacity` bytes
+    pub fn buffer(&self, capacity: usize) -> FrameBuffer {
+        let recycled = self
+            .shared
+            .free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+
+        let data = match recycled {
+            Some(mut data) if data.capacity() >= capacity => {
+                self.shared.reuses.fetch_add(1, Ordering::Relaxed);
+                data.clear();
+                data
+            }
+            Some(mut data) => {
+                self.shared.allocations.fetch_add(1, Ordering::Relaxed);
+                data.clear();
+                data.reserve(capacity);
+                data
+            }
+            None => {
+                self.shared.allocations.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(capacity)
+            }
+        };
+
+        FrameBuffer {
+            data,
+            pool: (self.shared.size > 0).then(|| self.shared.clone()),
+        }
+    }
+
+    /// Returns a buffer holding a copy of `src`
+    pub fn copy_from_slice(&self, src: &[u8]) -> FrameBuffer {
+        let mut buffer = self.buffer(src.len());
+        buffer.extend_from_slice(src);
+        buffer
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            allocations: self.shared.allocations.load(Ordering::Relaxed),
+            reuses: self.shared.reuses.load(Ordering::Relaxed),
+            idle: self
+                .shared
+                .free
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len(),
+        }
+    }
+}
+
+impl fmt::Debug for FramePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FramePool")
+            .field("size", &self.shared.size)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Pixel data of a video frame.
+///
+/// Derefs to the underlying `Vec<u8>`. Buffers taken from a [`FramePool`] go back to the pool
+/// when dropped; use [`FrameBuffer::into_vec`] to keep the data instead.
+#[derive(Default)]
+pub struct FrameBuffer {
+    data: Vec<u8>,
+    pool: Option<Arc<Shared>>,
+}
+
+impl FrameBuffer {
+    /// Takes the data out of the buffer, it will not be returned to its pool
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.pool = None;
+        std::mem::take(&mut self.data)
+    }
+}
+
+impl Drop for FrameBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.recycle(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+impl Deref for FrameBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.data
+    }
+}
+
+impl DerefMut for FrameBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.data
+    }
+}
+
+impl From<Vec<u8>> for FrameBuffer {
+    fn from(data: Vec<u8>) -> Self {
+        Self { data, pool: None }
+    }
+}
+
+impl From<FrameBuffer> for Vec<u8> {
+    fn from(buffer: FrameBuffer) -> Self {
+        buffer.into_vec()
+    }
+}
+
+/// Cloned buffers are detached from the pool
+impl Clone for FrameBuffer {
+    fn clone(&self) -> Self {
+        self.data.clone().into()
+    }
+}
+
+impl fmt::Debug for FrameBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+impl PartialEq for FrameBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl Eq for FrameBuffer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_buffers_are_reused() {
+        let pool = FramePool::new(2);
+        let first = pool.copy_from_slice(&[1; 16]);
+        let second = pool.copy_from_slice(&[2; 16]);
+        drop(first);
+        drop(second);
+        assert_eq!(pool.stats().idle, 2);
+
+        let third = pool.copy_from_slice(&[3; 8]);
+        assert_eq!(*third, vec![3; 8]);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                allocations: 2,
+                reuses: 1,
+                idle: 1,
+            }
+        );
+
+        // Detached data is not returned
+        let data = third.into_vec();
+        assert_eq!(data.len(), 8);
+        assert_eq!(pool.stats().idle, 1);
+    }
+
+    #[test]
+    fn zero_size_disables_recycling() {
+        let pool = FramePool::new(0);
+        drop(pool.copy_from_slice(&[0; 16]));
+        drop(pool.copy_from_slice(&[0; 16]));
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                allocations: 2,
+                reuses: 0,
+                idle: 0,
+            }
+        );
+    }
+}
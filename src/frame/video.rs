@@ -1,22 +1,63 @@
-use std::time::SystemTime;
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime},
+};
+
+use super::FrameBuffer;
+use crate::capturer::{Area, Point};
+
+/// Cursor state delivered alongside a frame instead of being drawn into its pixels
+#[derive(Debug, Clone)]
+pub struct CursorInfo {
+    /// Identifies the cursor image
+    pub id: u32,
+    /// Pointer position in frame coordinates
+    pub position: Point,
+    /// Offset of the pointer inside the cursor image, draw the image at `position - hotspot`
+    pub hotspot: Point,
+    /// New cursor image, only present on frames where the image changed
+    pub bitmap: Option<CursorBitmap>,
+}
+
+/// Cursor image as tightly packed BGRA pixels
+#[derive(Debug, Clone)]
+pub struct CursorBitmap {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<u8>,
+}
 
 #[derive(Debug, Clone)]
 pub struct YUVFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
     pub luminance_bytes: Vec<u8>,
     pub luminance_stride: i32,
     pub chrominance_bytes: Vec<u8>,
     pub chrominance_stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct RGBFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
-    pub data: Vec<u8>,
+    pub data: FrameBuffer,
+    /// Bytes per row in `data`, rows may be padded beyond `width` pixels
+    pub stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -29,41 +70,81 @@ pub struct RGB8Frame {
 #[derive(Debug, Clone)]
 pub struct RGBxFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
-    pub data: Vec<u8>,
+    pub data: FrameBuffer,
+    /// Bytes per row in `data`, rows may be padded beyond `width` pixels
+    pub stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct XBGRFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
-    pub data: Vec<u8>,
+    pub data: FrameBuffer,
+    /// Bytes per row in `data`, rows may be padded beyond `width` pixels
+    pub stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct BGRxFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
-    pub data: Vec<u8>,
+    pub data: FrameBuffer,
+    /// Bytes per row in `data`, rows may be padded beyond `width` pixels
+    pub stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct BGRFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
-    pub data: Vec<u8>,
+    pub data: FrameBuffer,
+    /// Bytes per row in `data`, rows may be padded beyond `width` pixels
+    pub stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct BGRAFrame {
     pub display_time: SystemTime,
+    pub pts: Duration,
+    pub sequence: u64,
     pub width: i32,
     pub height: i32,
-    pub data: Vec<u8>,
+    pub data: FrameBuffer,
+    /// Bytes per row in `data`, rows may be padded beyond `width` pixels
+    pub stride: i32,
+    pub damage: Option<Vec<Area>>,
+    pub cursor: Option<CursorInfo>,
+    pub repeat: bool,
+    pub source: usize,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -75,6 +156,37 @@ pub enum FrameType {
     BGRAFrame,
 }
 
+/// Pixel layout of the frames of a capture, one per [`VideoFrame`] variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    YUV,
+    RGB,
+    RGBx,
+    XBGR,
+    BGRx,
+    BGR0,
+    BGRA,
+}
+
+/// Runs `$body` on the frame inside any [`VideoFrame`] variant
+macro_rules! with_frame {
+    ($video:expr, $frame:ident => $body:expr) => {
+        match $video {
+            VideoFrame::YUVFrame($frame) => $body,
+            VideoFrame::RGB($frame) => $body,
+            VideoFrame::RGBx($frame) => $body,
+            VideoFrame::XBGR($frame) => $body,
+            VideoFrame::BGRx($frame) => $body,
+            VideoFrame::BGR0($frame) => $body,
+            VideoFrame::BGRA($frame) => $body,
+        }
+    };
+}
+
+/// A captured frame in one of the supported pixel formats.
+///
+/// The timing and metadata fields every frame struct shares are documented on the accessors
+/// below.
 #[derive(Debug, Clone)]
 pub enum VideoFrame {
     YUVFrame(YUVFrame),
@@ -86,6 +198,128 @@ pub enum VideoFrame {
     BGRA(BGRAFrame),
 }
 
+impl VideoFrame {
+    /// Wall clock time the frame was captured at
+    pub fn display_time(&self) -> SystemTime {
+        with_frame!(self, frame => frame.display_time)
+    }
+
+    /// Presentation timestamp on a monotonic clock, relative to the start of the capture
+    pub fn pts(&self) -> Duration {
+        with_frame!(self, frame => frame.pts)
+    }
+
+    /// Number of the frame within the capture, gaps mean frames were dropped on the way
+    pub fn sequence(&self) -> u64 {
+        with_frame!(self, frame => frame.sequence)
+    }
+
+    /// Regions that changed since the previous frame.
+    ///
+    /// Returns `None` when the source doesn't report damage, in which case the whole frame
+    /// has to be treated as changed.
+    pub fn damage(&self) -> Option<&[Area]> {
+        with_frame!(self, frame => frame.damage.as_deref())
+    }
+
+    /// Cursor state when the cursor is captured as metadata, see `Options::cursor_metadata`
+    pub fn cursor(&self) -> Option<&CursorInfo> {
+        with_frame!(self, frame => frame.cursor.as_ref())
+    }
+
+    /// Whether the frame is a copy of the previous one, sent because the source had nothing
+    /// new, see `Options::constant_frame_rate`
+    pub fn is_repeat(&self) -> bool {
+        with_frame!(self, frame => frame.repeat)
+    }
+
+    /// Index of the source the frame shows when capturing several sources with
+    /// `MultiSource::Separate`, `0` otherwise
+    pub fn source(&self) -> usize {
+        with_frame!(self, frame => frame.source)
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        match self {
+            VideoFrame::YUVFrame(_) => PixelFormat::YUV,
+            VideoFrame::RGB(_) => PixelFormat::RGB,
+            VideoFrame::RGBx(_) => PixelFormat::RGBx,
+            VideoFrame::XBGR(_) => PixelFormat::XBGR,
+            VideoFrame::BGRx(_) => PixelFormat::BGRx,
+            VideoFrame::BGR0(_) => PixelFormat::BGR0,
+            VideoFrame::BGRA(_) => PixelFormat::BGRA,
+        }
+    }
+
+    /// Replaces the timing of the frame
+    pub(crate) fn set_timing(&mut self, display_time: SystemTime, pts: Duration, sequence: u64) {
+        with_frame!(self, frame => {
+            frame.display_time = display_time;
+            frame.pts = pts;
+            frame.sequence = sequence;
+        })
+    }
+
+    /// Copy of the frame marked as a repeat, with nothing damaged and no new cursor image
+    pub(crate) fn to_repeat(&self) -> VideoFrame {
+        let mut repeat = self.clone();
+        with_frame!(&mut repeat, frame => {
+            frame.repeat = true;
+            frame.damage = Some(Vec::new());
+            if let Some(cursor) = &mut frame.cursor {
+                cursor.bitmap = None;
+            }
+        });
+        repeat
+    }
+}
+
+/// Splits `data` into `height` rows of `row_len` bytes laid out `stride` bytes apart
+fn rows(data: &[u8], stride: usize, row_len: usize, height: usize) -> impl Iterator<Item = &[u8]> {
+    data.chunks(stride.max(row_len).max(1))
+        .take(height)
+        .map(move |row| &row[..row.len().min(row_len)])
+}
+
+macro_rules! impl_packed_data {
+    ($($frame:ty => $bytes_per_pixel:expr),+ $(,)?) => {
+        $(
+            impl $frame {
+                pub const BYTES_PER_PIXEL: usize = $bytes_per_pixel;
+
+                /// Rows of pixel data, without the padding at the end of each row
+                pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+                    rows(
+                        &self.data,
+                        self.stride.max(0) as usize,
+                        self.width.max(0) as usize * Self::BYTES_PER_PIXEL,
+                        self.height.max(0) as usize,
+                    )
+                }
+
+                /// Pixel data with any row padding removed, borrowed if there is none
+                pub fn packed_data(&self) -> Cow<'_, [u8]> {
+                    let row_len = self.width.max(0) as usize * Self::BYTES_PER_PIXEL;
+                    if self.stride.max(0) as usize <= row_len || self.height <= 1 {
+                        let len = row_len * self.height.max(0) as usize;
+                        return Cow::Borrowed(&self.data[..self.data.len().min(len)]);
+                    }
+                    Cow::Owned(self.rows().flatten().copied().collect())
+                }
+            }
+        )+
+    };
+}
+
+impl_packed_data!(
+    RGBFrame => 3,
+    RGBxFrame => 4,
+    XBGRFrame => 4,
+    BGRxFrame => 4,
+    BGRFrame => 3,
+    BGRAFrame => 4,
+);
+
 pub enum FrameData<'a> {
     NV12(&'a YUVFrame),
     BGR0(&'a [u8]),
@@ -161,6 +395,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_packed_data() {
+        let frame = BGRxFrame {
+            display_time: SystemTime::UNIX_EPOCH,
+            pts: Duration::ZERO,
+            sequence: 0,
+            width: 2,
+            height: 2,
+            // Two pixels per row, padded to 12 bytes
+            data: vec![
+                1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 3, 3, 3, 3, 4, 4, 4, 4, 0, 0, 0, 0,
+            ]
+            .into(),
+            stride: 12,
+            damage: None,
+            cursor: None,
+            repeat: false,
+            source: 0,
+        };
+        assert_eq!(
+            *frame.packed_data(),
+            [1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4]
+        );
+
+        let packed = BGRxFrame {
+            data: vec![1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4].into(),
+            stride: 8,
+            ..frame
+        };
+        assert!(matches!(packed.packed_data(), Cow::Borrowed(_)));
+    }
+
     macro_rules! rgba {
         ($n:expr) => {
             &mut vec![$n, $n, $n, $n]
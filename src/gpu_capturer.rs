@@ -2,19 +2,24 @@ pub mod engine;
 
 use std::{
     sync::{Arc, mpsc},
-    time::SystemTime,
+    task::Poll,
+    time::{Duration, SystemTime},
 };
 
 use engine::{ChannelItem, Engine, EngineError, ProcessingError};
+use futures::Stream;
 
 use crate::{
-    capturer::Options,
+    capturer::{CaptureError, Options, ending_with_error, engine::channel},
     frame::{AudioFrame, FrameType},
     has_permission, is_supported,
 };
 
 /// Convenience re-exports so callers can configure the GPU capturer using the same types.
-pub use crate::capturer::{Area, Point, Resolution, Size};
+pub use crate::capturer::{
+    Area, InputDevice, MultiSource, ParentWindow, PersistMode, Point, PortalCancel, QueuePolicy,
+    Resolution, Size, SourceKind,
+};
 
 /// GPU-oriented frame emitted by [`GPUCapturer`].
 pub enum GpuFrame {
@@ -28,6 +33,8 @@ pub struct GpuVideoFrame {
     format: wgpu::TextureFormat,
     size: [u32; 2],
     display_time: SystemTime,
+    pts: Duration,
+    sequence: u64,
 }
 
 impl GpuVideoFrame {
@@ -51,11 +58,21 @@ impl GpuVideoFrame {
         self.size
     }
 
-    /// Timestamp derived from the original `CMSampleBuffer`.
+    /// Wall clock time the frame was captured at.
     pub fn display_time(&self) -> SystemTime {
         self.display_time
     }
 
+    /// Presentation timestamp on a monotonic clock, relative to the start of the capture.
+    pub fn pts(&self) -> Duration {
+        self.pts
+    }
+
+    /// Number of the frame within the capture, gaps mean frames were dropped on the way.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
     /// Creates a [`wgpu::TextureView`] for the captured texture.
     pub fn create_view(&self, desc: &wgpu::TextureViewDescriptor) -> wgpu::TextureView {
         self.texture.create_view(desc)
@@ -74,6 +91,8 @@ impl std::fmt::Debug for GpuVideoFrame {
             .field("size", &self.size)
             .field("format", &self.format)
             .field("display_time", &self.display_time)
+            .field("pts", &self.pts)
+            .field("sequence", &self.sequence)
             .finish_non_exhaustive()
     }
 }
@@ -100,6 +119,8 @@ pub enum GPUFrameError {
     Recv(#[from] mpsc::RecvError),
     #[error(transparent)]
     Processing(#[from] ProcessingError),
+    #[error(transparent)]
+    Capture(#[from] CaptureError),
 }
 
 /// Non-blocking polling error wrapper for [`GPUCapturer::try_get_next_frame`].
@@ -114,7 +135,7 @@ pub enum GPUFrameTryError {
 /// Screen capturer that yields zero-copy GPU textures backed by [`wgpu`].
 pub struct GPUCapturer {
     engine: Engine,
-    rx: mpsc::Receiver<ChannelItem>,
+    rx: channel::Receiver<ChannelItem>,
 }
 
 impl GPUCapturer {
@@ -136,7 +157,7 @@ impl GPUCapturer {
             return Err(GPUCapturerBuildError::UnsupportedOutputType);
         }
 
-        let (tx, rx) = mpsc::channel();
+        let (tx, rx) = channel::with_policy(options.queue_policy);
         let engine = match Engine::new(&options, device, queue, tx) {
             Ok(engine) => engine,
             Err(EngineError::Unsupported) => {
@@ -151,13 +172,15 @@ impl GPUCapturer {
     }
 
     /// Start capturing frames.
-    pub fn start_capture(&mut self) {
-        self.engine.start();
+    pub fn start_capture(&mut self) -> Result<(), CaptureError> {
+        self.rx.reopen();
+        self.engine.start()
     }
 
     /// Stop the capture session.
-    pub fn stop_capture(&mut self) {
-        self.engine.stop();
+    pub fn stop_capture(&mut self) -> Result<(), CaptureError> {
+        self.rx.close();
+        self.engine.stop()
     }
 
     /// Blocks until the next GPU frame (audio or video) is available.
@@ -189,6 +212,34 @@ impl GPUCapturer {
         }
     }
 
+    /// Returns a [`Stream`] of GPU frames that wakes the polling task when a frame arrives.
+    ///
+    /// The stream terminates once the capture engine stops delivering frames. If the capture
+    /// ends because of an error, the error is yielded before the stream terminates.
+    pub fn frames(&self) -> impl Stream<Item = Result<GpuFrame, GPUFrameError>> + '_ {
+        ending_with_error(
+            move |cx| {
+                loop {
+                    match self.rx.poll_recv(cx) {
+                        Poll::Ready(Some(item)) => match self.engine.process_channel_item(item) {
+                            Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                            Ok(None) => continue,
+                            Err(err) => return Poll::Ready(Some(Err(err.into()))),
+                        },
+                        Poll::Ready(None) => return Poll::Ready(None),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            },
+            || self.engine.error().map(GPUFrameError::from),
+        )
+    }
+
+    /// Number of frames dropped so far because of [`Options::queue_policy`].
+    pub fn dropped_frames(&self) -> u64 {
+        self.rx.dropped()
+    }
+
     /// Returns the negotiated capture dimensions (`[width, height]`).
     pub fn get_output_frame_size(&mut self) -> [u32; 2] {
         self.engine.get_output_frame_size()
@@ -200,3 +251,41 @@ impl From<GpuVideoFrame> for wgpu::Texture {
         value.texture
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::frame::AudioFormat;
+
+    fn audio() -> GpuFrame {
+        GpuFrame::Audio(AudioFrame::new(
+            AudioFormat::F32,
+            2,
+            false,
+            vec![0; 8],
+            1,
+            48_000,
+            SystemTime::UNIX_EPOCH,
+        ))
+    }
+
+    #[test]
+    fn frames_end_with_engine_error() {
+        // Stands in for the channel of a GPUCapturer whose engine failed after one frame
+        let mut items = VecDeque::from([audio()]);
+        let frames = ending_with_error(
+            |_| Poll::Ready(items.pop_front().map(Ok)),
+            || Some(GPUFrameError::from(CaptureError::Disconnected)),
+        );
+        let frames: Vec<_> = futures::executor::block_on_stream(frames).collect();
+        assert!(matches!(
+            frames[..],
+            [
+                Ok(GpuFrame::Audio(_)),
+                Err(GPUFrameError::Capture(CaptureError::Disconnected))
+            ]
+        ));
+    }
+}
@@ -1,6 +1,6 @@
-use std::sync::{Arc, mpsc};
+use std::sync::Arc;
 
-use crate::capturer::Options;
+use crate::capturer::{CaptureError, Options, engine::channel};
 
 use super::{GpuFrame, GpuVideoFrame};
 
@@ -56,7 +56,7 @@ impl Engine {
         options: &Options,
         device: Arc<wgpu::Device>,
         queue: Arc<wgpu::Queue>,
-        tx: mpsc::Sender<ChannelItem>,
+        tx: channel::Sender<ChannelItem>,
     ) -> Result<Self, EngineError> {
         #[cfg(target_os = "macos")]
         {
@@ -82,25 +82,51 @@ impl Engine {
         }
     }
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> Result<(), CaptureError> {
         #[cfg(target_os = "macos")]
         {
-            self.mac.start();
+            self.mac.start()
         }
-        #[cfg(target_os = "linux")]
+        #[cfg(all(not(target_os = "macos"), target_os = "linux"))]
+        {
+            self.linux.start()
+        }
+        #[cfg(all(not(target_os = "macos"), not(target_os = "linux")))]
         {
-            self.linux.start();
+            Ok(())
         }
     }
 
-    pub fn stop(&mut self) {
+    pub fn stop(&mut self) -> Result<(), CaptureError> {
         #[cfg(target_os = "macos")]
         {
-            self.mac.stop();
+            self.mac.stop()
         }
-        #[cfg(target_os = "linux")]
+        #[cfg(all(not(target_os = "macos"), target_os = "linux"))]
+        {
+            self.linux.stop()
+        }
+        #[cfg(all(not(target_os = "macos"), not(target_os = "linux")))]
+        {
+            Ok(())
+        }
+    }
+
+    /// Error that ended the capture, if any
+    pub fn error(&self) -> Option<CaptureError> {
+        #[cfg(target_os = "macos")]
+        {
+            self.mac.error()
+        }
+
+        #[cfg(all(not(target_os = "macos"), target_os = "linux"))]
+        {
+            self.linux.error()
+        }
+
+        #[cfg(all(not(target_os = "macos"), not(target_os = "linux")))]
         {
-            self.linux.stop();
+            None
         }
     }
 
@@ -151,11 +177,15 @@ pub(crate) fn build_video_frame(
     format: wgpu::TextureFormat,
     size: [u32; 2],
     display_time: std::time::SystemTime,
+    pts: std::time::Duration,
+    sequence: u64,
 ) -> GpuVideoFrame {
     GpuVideoFrame {
         texture,
         format,
         size,
         display_time,
+        pts,
+        sequence,
     }
 }
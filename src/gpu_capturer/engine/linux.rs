@@ -1,9 +1,9 @@
-use std::sync::{Arc, mpsc};
+use std::sync::Arc;
 
 use crate::{
-	capturer::Options,
+	capturer::{CaptureError, Options, engine::channel},
 	capturer::engine::linux::LinCapError,
-	frame::{BGRxFrame, Frame, RGBFrame, RGBxFrame, VideoFrame, XBGRFrame},
+	frame::{Frame, VideoFrame},
 };
 
 use super::{ChannelItem, build_video_frame, GpuFrame};
@@ -12,6 +12,9 @@ pub struct LinuxEngine {
 	device: Arc<wgpu::Device>,
 	queue: Arc<wgpu::Queue>,
 	output_size: std::cell::Cell<[u32; 2]>,
+	// Scratch buffers reused across frames for the BGRA conversion and the row padding
+	converted: std::cell::RefCell<Vec<u8>>,
+	padded: std::cell::RefCell<Vec<u8>>,
 	// Keep the CPU capturer alive and controllable
 	inner: crate::capturer::engine::linux::LinuxCapturer,
 }
@@ -39,13 +42,13 @@ impl LinuxEngine {
 		options: &Options,
 		device: Arc<wgpu::Device>,
 		queue: Arc<wgpu::Queue>,
-		tx: mpsc::Sender<ChannelItem>,
+		tx: channel::Sender<ChannelItem>,
 	) -> Result<Self, LinuxEngineError> {
 		// The CPU capturer constructor may fail due to portal issues.
 		let inner_result = std::panic::catch_unwind({
 			let options = options.clone();
 			let tx = tx.clone();
-			move || crate::capturer::engine::linux::try_create_capturer(&options, tx)
+			move || crate::capturer::engine::linux::create_capturer(&options, tx)
 		});
 
 		let inner = match inner_result {
@@ -71,20 +74,30 @@ impl LinuxEngine {
 			device,
 			queue,
 			output_size: std::cell::Cell::new([0, 0]),
+			converted: Default::default(),
+			padded: Default::default(),
 			inner,
 		})
 	}
 
-	pub fn start(&mut self) {
-		self.inner.start_capture();
+	pub fn start(&mut self) -> Result<(), CaptureError> {
+		self.inner.start_capture().map_err(CaptureError::from)
 	}
 
-	pub fn stop(&mut self) {
-		self.inner.stop_capture();
+	pub fn stop(&mut self) -> Result<(), CaptureError> {
+		self.inner.stop_capture().map_err(CaptureError::from)
+	}
+
+	pub fn error(&self) -> Option<CaptureError> {
+		self.inner.error().map(CaptureError::from)
 	}
 
 	pub fn get_output_frame_size(&self) -> [u32; 2] {
-		self.output_size.get()
+		// Until the first frame is uploaded the size is the one the CPU capturer negotiated
+		match self.output_size.get() {
+			[0, 0] => self.inner.output_frame_size(),
+			size => size,
+		}
 	}
 
 	pub fn process_channel_item(
@@ -101,38 +114,41 @@ impl LinuxEngine {
 		&self,
 		video: VideoFrame,
 	) -> Result<Option<GpuFrame>, LinuxProcessingError> {
-		let (display_time, width_i32, height_i32, converted_bgra) = match video {
-			VideoFrame::BGRx(BGRxFrame { display_time, width, height, data }) => {
+		let (display_time, pts, sequence) = (video.display_time(), video.pts(), video.sequence());
+		let mut converted_bgra = self.converted.borrow_mut();
+		converted_bgra.clear();
+		let (width_i32, height_i32) = match video {
+			VideoFrame::BGRx(frame) => {
 				// Convert BGRx -> BGRA (alpha=255)
-				let mut out = Vec::with_capacity((width as usize) * (height as usize) * 4);
-				for px in data.chunks_exact(4) {
-					out.extend_from_slice(&[px[0], px[1], px[2], 255]);
+				converted_bgra.reserve((frame.width as usize) * (frame.height as usize) * 4);
+				for px in frame.rows().flat_map(|row| row.chunks_exact(4)) {
+					converted_bgra.extend_from_slice(&[px[0], px[1], px[2], 255]);
 				}
-				(display_time, width, height, out)
+				(frame.width, frame.height)
 			}
-			VideoFrame::RGBx(RGBxFrame { display_time, width, height, data }) => {
+			VideoFrame::RGBx(frame) => {
 				// Convert RGBx -> BGRA
-				let mut out = Vec::with_capacity((width as usize) * (height as usize) * 4);
-				for px in data.chunks_exact(4) {
-					out.extend_from_slice(&[px[2], px[1], px[0], 255]);
+				converted_bgra.reserve((frame.width as usize) * (frame.height as usize) * 4);
+				for px in frame.rows().flat_map(|row| row.chunks_exact(4)) {
+					converted_bgra.extend_from_slice(&[px[2], px[1], px[0], 255]);
 				}
-				(display_time, width, height, out)
+				(frame.width, frame.height)
 			}
-			VideoFrame::XBGR(XBGRFrame { display_time, width, height, data }) => {
+			VideoFrame::XBGR(frame) => {
 				// Convert XBGR -> BGRA (drop leading X)
-				let mut out = Vec::with_capacity((width as usize) * (height as usize) * 4);
-				for px in data.chunks_exact(4) {
-					out.extend_from_slice(&[px[1], px[2], px[3], 255]);
+				converted_bgra.reserve((frame.width as usize) * (frame.height as usize) * 4);
+				for px in frame.rows().flat_map(|row| row.chunks_exact(4)) {
+					converted_bgra.extend_from_slice(&[px[1], px[2], px[3], 255]);
 				}
-				(display_time, width, height, out)
+				(frame.width, frame.height)
 			}
-			VideoFrame::RGB(RGBFrame { display_time, width, height, data }) => {
+			VideoFrame::RGB(frame) => {
 				// Convert RGB -> BGRA
-				let mut out = Vec::with_capacity((width as usize) * (height as usize) * 4);
-				for px in data.chunks_exact(3) {
-					out.extend_from_slice(&[px[2], px[1], px[0], 255]);
+				converted_bgra.reserve((frame.width as usize) * (frame.height as usize) * 4);
+				for px in frame.rows().flat_map(|row| row.chunks_exact(3)) {
+					converted_bgra.extend_from_slice(&[px[2], px[1], px[0], 255]);
 				}
-				(display_time, width, height, out)
+				(frame.width, frame.height)
 			}
 			_ => return Err(LinuxProcessingError::UnsupportedFormat),
 		};
@@ -174,7 +190,9 @@ impl LinuxEngine {
 				wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
 			);
 		} else {
-			let mut padded = vec![0u8; (padded_bpr * height) as usize];
+			let mut padded = self.padded.borrow_mut();
+			padded.clear();
+			padded.resize((padded_bpr * height) as usize, 0);
 			for row in 0..height as usize {
 				let src_off = row * bytes_per_row as usize;
 				let dst_off = row * padded_bpr as usize;
@@ -198,7 +216,14 @@ impl LinuxEngine {
 			);
 		}
 
-		let video = build_video_frame(texture, wgpu::TextureFormat::Bgra8Unorm, [width, height], display_time);
+		let video = build_video_frame(
+			texture,
+			wgpu::TextureFormat::Bgra8Unorm,
+			[width, height],
+			display_time,
+			pts,
+			sequence,
+		);
 		Ok(Some(GpuFrame::Video(video)))
 	}
 }
@@ -3,12 +3,11 @@ use std::{
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
-        mpsc,
     },
-    time::{Duration, SystemTime},
+    time::SystemTime,
 };
 
-use cidre::{arc, cm, mach, sc};
+use cidre::{arc, cm, sc};
 use core_foundation::base::CFRelease;
 use metal::{foreign_types::ForeignType, Device, MTLPixelFormat, MTLTexture, MTLTextureType, MTLTextureUsage, Texture};
 use objc2::{
@@ -35,7 +34,9 @@ use wgpu::hal::{CopyExtent, api::Metal as HalMetal};
 use super::{ChannelItem, build_video_frame};
 use crate::{
     capturer::{
-        Options,
+        CaptureError, Options,
+        engine::channel,
+        engine::clock::{FrameClock, to_system_time},
         engine::mac as cpu_mac,
         engine::mac::{Capturer, ErrorHandler, get_output_frame_size as cpu_output_frame_size},
     },
@@ -49,6 +50,7 @@ pub struct MacEngine {
     error_flag: Arc<AtomicBool>,
     texture_cache: MetalTextureCache,
     device: Arc<wgpu::Device>,
+    clock: FrameClock,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -95,7 +97,7 @@ impl MacEngine {
     pub fn new(
         options: &Options,
         device: Arc<wgpu::Device>,
-        tx: mpsc::Sender<ChannelItem>,
+        tx: channel::Sender<ChannelItem>,
     ) -> Result<Self, MacEngineError> {
         let error_flag = Arc::new(AtomicBool::new(false));
         let capturer = cpu_mac::create_capturer(options, tx, error_flag.clone())
@@ -114,15 +116,24 @@ impl MacEngine {
             error_flag,
             texture_cache,
             device,
+            clock: FrameClock::default(),
         })
     }
 
-    pub fn start(&self) {
-        futures::executor::block_on(self.capturer.2.start()).expect("Failed to start capture");
+    pub fn start(&self) -> Result<(), CaptureError> {
+        futures::executor::block_on(self.capturer.2.start())
+            .map_err(|e| CaptureError::Backend(format!("Failed to start capture: {e:?}")))
     }
 
-    pub fn stop(&self) {
-        futures::executor::block_on(self.capturer.2.stop()).expect("Failed to stop capture");
+    pub fn stop(&self) -> Result<(), CaptureError> {
+        futures::executor::block_on(self.capturer.2.stop())
+            .map_err(|e| CaptureError::Backend(format!("Failed to stop capture: {e:?}")))
+    }
+
+    pub fn error(&self) -> Option<CaptureError> {
+        self.error_flag
+            .load(Ordering::Relaxed)
+            .then(|| CaptureError::Backend(String::from("Screen capture stream stopped")))
     }
 
     pub fn get_output_frame_size(&self, options: &Options) -> [u32; 2] {
@@ -153,6 +164,7 @@ impl MacEngine {
             sample,
             sc::stream::OutputType::Audio,
             FrameType::BGRAFrame,
+            &self.clock,
         )
         .ok_or(MacProcessingError::AudioConversion)?;
 
@@ -180,7 +192,8 @@ impl MacEngine {
             other => return Err(MacProcessingError::UnknownFrameStatus(other)),
         }
 
-        let display_time = compute_display_time(sample.as_ref());
+        let (capture_time, now) = cpu_mac::host_times(sample.as_ref());
+        let display_time = to_system_time(capture_time, now, SystemTime::now());
 
         let image_buffer = sample
             .image_buf()
@@ -262,34 +275,19 @@ impl MacEngine {
             )
         };
 
-        let video = build_video_frame(texture, format, [width, height], display_time);
+        let (pts, sequence) = self.clock.video(capture_time);
+        let video = build_video_frame(
+            texture,
+            format,
+            [width, height],
+            display_time,
+            pts,
+            sequence,
+        );
         Ok(Some(super::GpuFrame::Video(video)))
     }
 }
 
-fn compute_display_time(sample: &cm::SampleBuf) -> SystemTime {
-    let system_time = SystemTime::now();
-    let system_mach_time = mach::abs_time();
-
-    let frame_cm_time = sample.pts();
-    let frame_mach_time = cm::Clock::convert_host_time_to_sys_units(frame_cm_time);
-
-    let mach_time_diff = if frame_mach_time > system_mach_time {
-        (frame_mach_time - system_mach_time) as i64
-    } else {
-        -((system_mach_time - frame_mach_time) as i64)
-    };
-
-    let mach_timebase = mach::TimeBaseInfo::new();
-    let nanos_diff = (mach_time_diff * mach_timebase.numer as i64) / mach_timebase.denom as i64;
-
-    if nanos_diff >= 0 {
-        system_time + Duration::from_nanos(nanos_diff as u64)
-    } else {
-        system_time - Duration::from_nanos((-nanos_diff) as u64)
-    }
-}
-
 fn map_pixel_format(format: MTLPixelFormat) -> Result<wgpu::TextureFormat, MacProcessingError> {
     match format {
         MTLPixelFormat::BGRA8Unorm => Ok(wgpu::TextureFormat::Bgra8Unorm),
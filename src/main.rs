@@ -53,7 +53,10 @@ fn main() {
     });
 
     // Start Capture
-    recorder.start_capture();
+    if let Err(err) = recorder.start_capture() {
+        println!("Problem with starting capture: {err}");
+        process::exit(1);
+    }
 
     // Capture 100 frames
     for i in 0..100 {
@@ -115,5 +118,7 @@ fn main() {
     }
 
     // Stop Capture
-    recorder.stop_capture();
+    if let Err(err) = recorder.stop_capture() {
+        println!("Problem with stopping capture: {err}");
+    }
 }
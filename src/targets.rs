@@ -7,6 +7,11 @@ mod win;
 #[cfg(target_os = "linux")]
 mod linux;
 
+#[cfg(target_os = "linux")]
+pub use linux::PortalStream;
+#[cfg(target_os = "linux")]
+pub(crate) use linux::remember_portal_targets;
+
 #[derive(Debug, Clone)]
 pub struct Window {
     pub id: u32,
@@ -17,6 +22,9 @@ pub struct Window {
 
     #[cfg(target_os = "macos")]
     pub raw_handle: cidre::cg::WindowId,
+
+    #[cfg(target_os = "linux")]
+    pub raw_handle: PortalStream,
 }
 
 #[derive(Debug, Clone)]
@@ -29,12 +37,49 @@ pub struct Display {
 
     #[cfg(target_os = "macos")]
     pub raw_handle: cidre::cg::DirectDisplayId,
+
+    #[cfg(target_os = "linux")]
+    pub raw_handle: PortalStream,
+}
+
+/// Pattern drawn by a [`Synthetic`] target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Static vertical color bars
+    #[default]
+    ColorBars,
+    /// A white square bouncing around a dark background
+    MovingBox,
+}
+
+/// Generated test source that needs no display server, portal or capture permission.
+///
+/// Every frame has the frame number and its timestamp burned into the top left corner.
+#[derive(Debug, Clone)]
+pub struct Synthetic {
+    pub width: u32,
+    pub height: u32,
+    pub pattern: TestPattern,
+    /// Frequency in Hz of a sine tone delivered as audio when audio capture is enabled
+    pub audio_tone: Option<f32>,
+}
+
+impl Default for Synthetic {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            pattern: TestPattern::default(),
+            audio_tone: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Target {
     Window(Window),
     Display(Display),
+    Synthetic(Synthetic),
 }
 
 /// Returns a list of targets that can be captured
@@ -50,6 +95,10 @@ pub fn get_all_targets() -> Vec<Target> {
 }
 
 pub fn get_scale_factor(target: &Target) -> f64 {
+    if let Target::Synthetic(_) = target {
+        return 1.0;
+    }
+
     #[cfg(target_os = "macos")]
     return mac::get_scale_factor(target);
 
@@ -68,10 +117,26 @@ pub fn get_main_display() -> Display {
     return win::get_main_display();
 
     #[cfg(target_os = "linux")]
-    unreachable!();
+    return linux::get_main_display();
+}
+
+/// Whether `target` leaves picking the source to the user, as the portal dialog on Linux does
+pub(crate) fn is_user_selection(target: &Target) -> bool {
+    #[cfg(target_os = "linux")]
+    return linux::is_portal_selection(target);
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = target;
+        false
+    }
 }
 
 pub fn get_target_dimensions(target: &Target) -> (u64, u64) {
+    if let Target::Synthetic(source) = target {
+        return (source.width as u64, source.height as u64);
+    }
+
     #[cfg(target_os = "macos")]
     return mac::get_target_dimensions(target);
 
@@ -79,5 +144,5 @@ pub fn get_target_dimensions(target: &Target) -> (u64, u64) {
     return win::get_target_dimensions(target);
 
     #[cfg(target_os = "linux")]
-    unreachable!();
+    return linux::get_target_dimensions(target);
 }
@@ -1,7 +1,240 @@
-use super::Target;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
 
-// On Linux, the target is selected when a Recorder is instanciated because this
-// requires user interaction
+use pipewire as pw;
+use pw::spa::utils::result::SpaResult;
+
+use super::{Display, Target};
+use crate::capturer::SourceKind;
+
+/// A source the user picked in the ScreenCast portal dialog
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortalStream {
+    /// PipeWire node the frames of the source are delivered on
+    pub node_id: u32,
+    /// Identifies the stream within the session, kept when the session is restored
+    pub id: Option<String>,
+    /// Position on the virtual desktop, only reported for monitors
+    pub position: Option<(i32, i32)>,
+    /// Size in logical pixels
+    pub size: Option<(i32, i32)>,
+    /// Kind of the source, if the portal reported it
+    pub source_type: Option<SourceKind>,
+    /// Links the stream to the regions of the RemoteDesktop portal's absolute pointer events
+    pub mapping_id: Option<String>,
+}
+
+/// How long listing the PipeWire registry may take before the portal targets are used alone
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Sources granted by earlier portal sessions of this process
+static PORTAL_TARGETS: Mutex<Vec<Target>> = Mutex::new(Vec::new());
+
+/// Remembers the sources of a portal session so they are listed by [`get_all_targets`]
+pub(crate) fn remember_portal_targets(targets: &[Target]) {
+    let mut known = PORTAL_TARGETS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    known.retain(|target| !targets.iter().any(|new| node_id(new) == node_id(target)));
+    known.extend_from_slice(targets);
+}
+
+fn node_id(target: &Target) -> Option<u32> {
+    match target {
+        Target::Display(display) => Some(display.raw_handle.node_id),
+        Target::Window(window) => Some(window.raw_handle.node_id),
+        Target::Synthetic(_) => None,
+    }
+}
+
+// On Wayland, sources can only be picked through the portal dialog, so the list starts with
+// the target standing for that selection. It is followed by the sources granted to earlier
+// portal sessions whose streams still exist. Only the selection can be passed to
+// `Options::target`, the others identify the sources of those sessions
 pub fn get_all_targets() -> Vec<Target> {
-    Vec::new()
+    let mut targets = vec![Target::Display(get_main_display())];
+    let granted = PORTAL_TARGETS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    // Without a reachable PipeWire daemon the streams can't be checked, so all are kept
+    match video_source_nodes() {
+        Ok(nodes) => targets.extend(
+            granted
+                .into_iter()
+                .filter(|target| node_id(target).is_some_and(|id| nodes.contains(&id))),
+        ),
+        Err(_) => targets.extend(granted),
+    }
+
+    targets
+}
+
+/// Whether `target` is the one standing for the selection in the portal dialog
+pub(crate) fn is_portal_selection(target: &Target) -> bool {
+    matches!(target, Target::Display(display) if display.raw_handle == PortalStream::default())
+}
+
+/// Target that lets the user pick the source in the portal dialog when capturing
+pub fn get_main_display() -> Display {
+    Display {
+        id: 0,
+        title: String::from("Selected in the portal dialog"),
+        raw_handle: PortalStream::default(),
+    }
+}
+
+/// Logical size the portal reported for the source, `(0, 0)` until it is known
+pub fn get_target_dimensions(target: &Target) -> (u64, u64) {
+    let size = match target {
+        Target::Display(display) => display.raw_handle.size,
+        Target::Window(window) => window.raw_handle.size,
+        Target::Synthetic(source) => Some((source.width as i32, source.height as i32)),
+    };
+    size.map_or((0, 0), |(width, height)| {
+        (width.max(0) as u64, height.max(0) as u64)
+    })
+}
+
+/// Ids of the `Video/Source` nodes in the PipeWire registry, portal streams among them
+fn video_source_nodes() -> Result<Vec<u32>, pw::Error> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+    let registry = core.get_registry()?;
+
+    let nodes = Rc::new(RefCell::new(Vec::new()));
+    let _registry_listener = registry
+        .add_listener_local()
+        .global({
+            let nodes = nodes.clone();
+            move |global| {
+                let is_video_source = global.type_ == pw::types::ObjectType::Node
+                    && global.props.is_some_and(|props| {
+                        props.get(*pw::keys::MEDIA_CLASS) == Some("Video/Source")
+                    });
+                if is_video_source {
+                    nodes.borrow_mut().push(global.id);
+                }
+            }
+        })
+        .register();
+
+    // Wait until the daemon answered a sync, by then every existing global was announced.
+    // An error or a daemon that doesn't answer in time ends the wait with that result
+    let result = Rc::new(Cell::new(None));
+    let pending = core.sync(0)?;
+    let _core_listener = core
+        .add_listener_local()
+        .done({
+            let result = result.clone();
+            let mainloop = mainloop.clone();
+            move |id, seq| {
+                if id == pw::core::PW_ID_CORE && seq == pending {
+                    result.set(Some(0));
+                    mainloop.quit();
+                }
+            }
+        })
+        .error({
+            let result = result.clone();
+            let mainloop = mainloop.clone();
+            move |_id, _seq, res, _message| {
+                result.set(Some(res.min(-1)));
+                mainloop.quit();
+            }
+        })
+        .register();
+    let timer = mainloop.loop_().add_timer({
+        let result = result.clone();
+        let mainloop = mainloop.clone();
+        move |_| {
+            result.set(Some(-libc::ETIMEDOUT));
+            mainloop.quit();
+        }
+    });
+    timer
+        .update_timer(Some(REGISTRY_TIMEOUT), None)
+        .into_result()?;
+    let res = loop {
+        match result.get() {
+            Some(res) => break res,
+            None => mainloop.run(),
+        }
+    };
+    SpaResult::from_c(res).into_result()?;
+
+    Ok(nodes.take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        capturer::{Capabilities, Options, OptionsError, UnsupportedOption},
+        targets::Window,
+    };
+
+    fn window(node_id: u32, title: &str) -> Target {
+        Target::Window(Window {
+            id: node_id,
+            title: String::from(title),
+            raw_handle: PortalStream {
+                node_id,
+                size: Some((800, 600)),
+                source_type: Some(SourceKind::Window),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn remembers_portal_targets_by_node() {
+        remember_portal_targets(&[window(1001, "old")]);
+        remember_portal_targets(&[window(1001, "new"), window(1002, "other")]);
+
+        let known = PORTAL_TARGETS.lock().unwrap();
+        let titles: Vec<_> = known
+            .iter()
+            .filter_map(|target| match target {
+                Target::Window(window) if window.id > 1000 => Some(window.title.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(titles, ["new", "other"]);
+        let renewed = known
+            .iter()
+            .find(|target| node_id(target) == Some(1001))
+            .unwrap();
+        assert_eq!(get_target_dimensions(renewed), (800, 600));
+        assert_eq!(
+            get_target_dimensions(&Target::Display(get_main_display())),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn only_the_portal_selection_can_be_requested() {
+        let options = |target| Options {
+            fps: 30,
+            target: Some(target),
+            ..Default::default()
+        };
+        assert_eq!(
+            options(window(1003, "granted")).validate(&Capabilities::default()),
+            Err(OptionsError::Unsupported(vec![UnsupportedOption::Target]))
+        );
+        assert!(
+            options(Target::Display(get_main_display()))
+                .validate(&Capabilities::default())
+                .is_ok()
+        );
+    }
 }
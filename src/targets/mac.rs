@@ -89,6 +89,7 @@ pub fn get_scale_factor(target: &Target) -> f64 {
             let width = CGDisplayMode::width(Some(&mode)) as f64;
             pixel_width / width
         }
+        Target::Synthetic(_) => 1.0,
     }
 }
 
@@ -112,5 +113,6 @@ pub fn get_target_dimensions(target: &Target) -> (u64, u64) {
             let height = CGDisplayMode::height(Some(&mode)) as u64;
             (width, height)
         }
+        Target::Synthetic(source) => (source.width as u64, source.height as u64),
     }
 }
@@ -74,6 +74,7 @@ pub fn get_scale_factor(target: &Target) -> f64 {
                 BASE_DPI
             }
         },
+        Target::Synthetic(_) => BASE_DPI,
     };
 
     let scale_factor = dpi as f64 / BASE_DPI as f64;
@@ -101,5 +102,6 @@ pub fn get_target_dimensions(target: &Target) -> (u64, u64) {
                 monitor.height().unwrap() as u64,
             )
         }
+        Target::Synthetic(source) => (source.width as u64, source.height as u64),
     }
 }